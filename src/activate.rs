@@ -0,0 +1,736 @@
+use std::{collections::HashMap, hash::BuildHasher, path::Path};
+
+use crate::{
+    config,
+    database::{self, InstallRecord},
+    environment,
+    escape::{csh_quoted, fish_quoted, sh_quoted},
+    file_manager::PATH_SEP,
+    flavours::{self, Selection},
+    module::{get_modules, Dependency, Environment, Module},
+    modulefile::installed_flavours,
+};
+
+/// A flavour of a module: the modules it was built against, of which the
+/// first `.1` select the flavour
+type Flavour = (Vec<Module>, usize);
+
+/// Prefix of the variables holding the values replaced by an activation, so
+/// that deactivating can restore them
+const SAVED_PREFIX: &str = "SCCMOD_SAVED_";
+
+/// The syntax of a shell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Bash,
+    Zsh,
+    Fish,
+    Csh,
+}
+
+/// Parse the name of, or path to, a shell, e.g. `zsh` or `/bin/tcsh`.
+///
+/// # Errors
+/// Errors if the shell is not supported.
+pub fn dialect(shell: &str) -> Result<Dialect, String> {
+    match shell.rsplit(PATH_SEP).next().unwrap_or(shell) {
+        "bash" | "sh" => Ok(Dialect::Bash),
+        "zsh" => Ok(Dialect::Zsh),
+        "fish" => Ok(Dialect::Fish),
+        "csh" | "tcsh" => Ok(Dialect::Csh),
+        other => Err(format!(
+            "Unsupported shell '{other}'. Expected bash, zsh, fish or csh"
+        )),
+    }
+}
+
+/// The dialect of the user's shell, from `$SHELL`, falling back to bash
+#[must_use]
+pub fn login_shell() -> Dialect {
+    std::env::var("SHELL")
+        .ok()
+        .and_then(|shell| dialect(&shell).ok())
+        .unwrap_or(Dialect::Bash)
+}
+
+/// The first installed flavour of `module` chosen by `selection`
+///
+/// # Errors
+/// Errors if the flavours of `module` cannot be generated, or none of the
+/// installed ones is selected.
+pub fn select_flavour(
+    module: &Module,
+    modules: &[Module],
+    records: &[InstallRecord],
+    selection: &Selection,
+) -> Result<Flavour, String> {
+    installed_flavours(module, modules, records)?
+        .into_iter()
+        .find(|flav| selection.matches(flav))
+        .ok_or_else(|| {
+            format!(
+                "No installed flavour of '{}' matches the selection",
+                module.identifier()
+            )
+        })
+}
+
+/// The modules `flavour` of `module` needs loaded: those it was built
+/// against, and its `Depends` dependencies managed by sccmod
+fn runtime_dependencies(
+    module: &Module,
+    flavour: &Flavour,
+    modules: &[Module],
+) -> Result<Vec<Module>, String> {
+    let mut dependencies: Vec<Module> =
+        flavour.0.iter().take(flavour.1).cloned().collect();
+
+    for dep in &module.dependencies {
+        if let Dependency::Depends(name) = dep {
+            match flavours::resolve_dependency(modules, name) {
+                Ok(dependency) => dependencies.push(dependency.clone()),
+                // Modules not managed by sccmod cannot be activated
+                Err(_) if !name.contains('@') => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// The installed flavour of `dependency` to load alongside `flavour`,
+/// preferring one built against the same modules
+fn dependency_flavour(
+    dependency: &Module,
+    flavour: &Flavour,
+    modules: &[Module],
+    records: &[InstallRecord],
+) -> Result<Flavour, String> {
+    let in_flavour =
+        |m: &Module| flavour.0.iter().any(|f| f.identifier() == m.identifier());
+
+    let installed = installed_flavours(dependency, modules, records)?;
+
+    installed
+        .iter()
+        .find(|flav| flav.0.iter().take(flav.1).all(in_flavour))
+        .or_else(|| installed.first())
+        .cloned()
+        .ok_or_else(|| {
+            format!("Dependency '{}' is not installed", dependency.identifier())
+        })
+}
+
+/// Add `flavour` of `module` to `order`, after its dependencies
+fn visit(
+    module: &Module,
+    flavour: Flavour,
+    modules: &[Module],
+    records: &[InstallRecord],
+    order: &mut Vec<(Module, Flavour)>,
+    seen: &mut Vec<String>,
+) -> Result<(), String> {
+    if seen.contains(&module.identifier()) {
+        return Ok(());
+    }
+    seen.push(module.identifier());
+
+    for dependency in runtime_dependencies(module, &flavour, modules)? {
+        let dependency_flavour =
+            dependency_flavour(&dependency, &flavour, modules, records)?;
+
+        visit(&dependency, dependency_flavour, modules, records, order, seen)?;
+    }
+
+    order.push((module.clone(), flavour));
+    Ok(())
+}
+
+/// The module flavours to load for `flavour` of `module`, including its
+/// transitive dependencies, each after the modules it depends on.
+///
+/// # Errors
+/// Errors if a dependency cannot be resolved or is not installed.
+pub fn load_order(
+    module: &Module,
+    flavour: Flavour,
+    modules: &[Module],
+    records: &[InstallRecord],
+) -> Result<Vec<(Module, Flavour)>, String> {
+    let mut order = Vec::new();
+    visit(module, flavour, modules, records, &mut order, &mut Vec::new())?;
+    Ok(order)
+}
+
+/// `value` with its path made absolute by prefixing `root`, unless it is
+/// already absolute
+fn absolute(root: &str, value: Environment) -> Environment {
+    let path = |val: String| {
+        if val.is_empty() {
+            root.to_string()
+        } else if Path::new(&val).is_absolute() {
+            val
+        } else {
+            format!("{root}{PATH_SEP}{val}")
+        }
+    };
+
+    match value {
+        Environment::Set(val) => Environment::SetExact(path(val)),
+        Environment::Append(val, sep) => Environment::Append(path(val), sep),
+        Environment::Prepend(val, sep) => Environment::Prepend(path(val), sep),
+        Environment::RemovePath(val, sep) => {
+            Environment::RemovePath(path(val), sep)
+        }
+        Environment::SourceSh(script) => Environment::SourceSh(path(script)),
+        other => other,
+    }
+}
+
+/// The environment of each of `loads` in turn, with absolute paths, including
+/// the paths detected in `records`
+#[must_use]
+pub fn operations(
+    loads: &[(Module, Flavour)],
+    records: &[InstallRecord],
+) -> Vec<(String, Environment)> {
+    loads
+        .iter()
+        .flat_map(|(module, flavour)| {
+            let module = database::at_latest_revision(records, module, flavour);
+            let (_, _, root, _) = module.parse(&(&flavour.0, flavour.1));
+
+            environment::resolve(
+                &module,
+                records,
+                Some(&flavours::gen_name(flavour)),
+            )
+            .into_iter()
+            .map(|(key, value)| (key, absolute(&root, value)))
+            .collect::<Vec<(String, Environment)>>()
+        })
+        .collect()
+}
+
+/// The environment of the installed flavour of `module` chosen by
+/// `selection`, preceded by that of its dependencies, with absolute paths.
+///
+/// # Errors
+/// Errors if the modules or install records cannot be read, no installed
+/// flavour is selected or a dependency is not installed.
+pub fn resolve(
+    module: &Module,
+    selection: &Selection,
+    config: &config::Config,
+) -> Result<Vec<(String, Environment)>, String> {
+    let modules = get_modules()?;
+    let records = database::records(config)?;
+
+    let flavour = select_flavour(module, &modules, &records, selection)?;
+    let loads = load_order(module, flavour, &modules, &records)?;
+
+    Ok(operations(&loads, &records))
+}
+
+/// Remove the entries equal to `entry` from the path list `key` of `vars`,
+/// unsetting it once empty
+fn remove_entry<S: BuildHasher>(
+    vars: &mut HashMap<String, String, S>,
+    key: &str,
+    entry: &str,
+    separator: Option<&str>,
+) {
+    let separator = separator.unwrap_or(":");
+
+    let remaining = vars.get(key).map(|current| {
+        current
+            .split(separator)
+            .filter(|e| !e.is_empty() && *e != entry)
+            .collect::<Vec<&str>>()
+            .join(separator)
+    });
+
+    match remaining {
+        Some(remaining) if !remaining.is_empty() => {
+            vars.insert(key.to_string(), remaining);
+        }
+        _ => {
+            vars.remove(key);
+        }
+    }
+}
+
+/// Apply `value` to the variable `key` of `vars`, as loading a modulefile
+/// would.
+///
+/// Entries added to a path list are moved if already present. Aliases,
+/// functions and sourced scripts change no variables, so they are ignored.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use sccmod::{activate::apply, module::Environment};
+///
+/// let mut vars = HashMap::from([("PATH".to_string(), "/usr/bin:/opt/bin".to_string())]);
+/// apply(&mut vars, "PATH", &Environment::Prepend("/opt/bin".into(), None));
+/// apply(&mut vars, "OLD", &Environment::Unset);
+///
+/// assert_eq!(vars["PATH"], "/opt/bin:/usr/bin");
+/// assert!(!vars.contains_key("OLD"));
+/// ```
+pub fn apply<S: BuildHasher>(
+    vars: &mut HashMap<String, String, S>,
+    key: &str,
+    value: &Environment,
+) {
+    match value {
+        Environment::Set(val) | Environment::SetExact(val) => {
+            vars.insert(key.to_string(), val.clone());
+        }
+        Environment::Append(val, sep) | Environment::Prepend(val, sep) => {
+            remove_entry(vars, key, val, sep.as_deref());
+
+            let separator = sep.as_deref().unwrap_or(":");
+            let updated = match (vars.get(key), value) {
+                (None, _) => val.clone(),
+                (Some(current), Environment::Append(..)) => {
+                    format!("{current}{separator}{val}")
+                }
+                (Some(current), _) => format!("{val}{separator}{current}"),
+            };
+
+            vars.insert(key.to_string(), updated);
+        }
+        Environment::RemovePath(val, sep) => {
+            remove_entry(vars, key, val, sep.as_deref());
+        }
+        Environment::Unset => {
+            vars.remove(key);
+        }
+        Environment::SetAlias(_)
+        | Environment::SetFunction(_)
+        | Environment::SourceSh(_) => {}
+    }
+}
+
+/// Undo [`apply`]ing `value` to `key`: added path entries are removed, and
+/// replaced values are restored from the variables saved by [`script`]
+fn revert<S: BuildHasher>(
+    vars: &mut HashMap<String, String, S>,
+    key: &str,
+    value: &Environment,
+) {
+    match value {
+        Environment::Append(val, sep) | Environment::Prepend(val, sep) => {
+            remove_entry(vars, key, val, sep.as_deref());
+        }
+        Environment::Set(_) | Environment::SetExact(_) | Environment::Unset => {
+            match vars.remove(&format!("{SAVED_PREFIX}{key}")) {
+                Some(saved) => vars.insert(key.to_string(), saved),
+                None => vars.remove(key),
+            };
+        }
+        // A removed entry cannot be put back in its place
+        Environment::RemovePath(..)
+        | Environment::SetAlias(_)
+        | Environment::SetFunction(_)
+        | Environment::SourceSh(_) => {}
+    }
+}
+
+/// The statement setting `key` to `value`
+fn export(dialect: Dialect, key: &str, value: &str) -> String {
+    match dialect {
+        Dialect::Bash | Dialect::Zsh => {
+            format!("export {key}={};\n", sh_quoted(value))
+        }
+        // fish keeps path variables as lists
+        Dialect::Fish if key.ends_with("PATH") => format!(
+            "set -gx {key} {};\n",
+            value
+                .split(':')
+                .map(fish_quoted)
+                .collect::<Vec<String>>()
+                .join(" ")
+        ),
+        Dialect::Fish => format!("set -gx {key} {};\n", fish_quoted(value)),
+        Dialect::Csh => format!("setenv {key} {};\n", csh_quoted(value)),
+    }
+}
+
+/// The statement unsetting `key`
+fn unset(dialect: Dialect, key: &str) -> String {
+    match dialect {
+        Dialect::Bash | Dialect::Zsh => format!("unset {key};\n"),
+        Dialect::Fish => format!("set -e {key};\n"),
+        Dialect::Csh => format!("unsetenv {key};\n"),
+    }
+}
+
+/// Returns `true` if a script written for `shell` can be sourced by `dialect`
+fn sources(dialect: Dialect, shell: &str) -> bool {
+    matches!(
+        (dialect, shell),
+        (Dialect::Bash, "bash" | "sh")
+            | (Dialect::Zsh, "zsh" | "sh")
+            | (Dialect::Fish, "fish")
+            | (Dialect::Csh, "csh" | "tcsh")
+    )
+}
+
+/// The statement defining, or removing if `deactivate` is set, an alias or
+/// function, or sourcing a script. Variables are handled by [`script`].
+fn statement(
+    dialect: Dialect,
+    key: &str,
+    value: &Environment,
+    deactivate: bool,
+) -> Option<String> {
+    let statement = match (value, dialect, deactivate) {
+        (
+            Environment::SetAlias(_) | Environment::SetFunction(_),
+            Dialect::Fish,
+            true,
+        ) => {
+            format!("functions -e {key};\n")
+        }
+        (Environment::SetAlias(_), _, true) => format!("unalias {key};\n"),
+        (Environment::SetFunction(_), Dialect::Bash | Dialect::Zsh, true) => {
+            format!("unset -f {key};\n")
+        }
+        (Environment::SetAlias(body), Dialect::Bash | Dialect::Zsh, false) => {
+            format!("alias {key}={};\n", sh_quoted(body))
+        }
+        (Environment::SetAlias(body), Dialect::Fish, false) => {
+            format!("alias {key} {};\n", fish_quoted(body))
+        }
+        (Environment::SetAlias(body), Dialect::Csh, false) => {
+            format!("alias {key} {};\n", csh_quoted(body))
+        }
+        (
+            Environment::SetFunction(body),
+            Dialect::Bash | Dialect::Zsh,
+            false,
+        ) => format!("{key}() {{ {body}; }};\n"),
+        (Environment::SetFunction(body), Dialect::Fish, false) => {
+            format!("function {key}; {body}; end;\n")
+        }
+        (Environment::SourceSh(script), _, false) if sources(dialect, key) => {
+            match dialect {
+                Dialect::Bash | Dialect::Zsh => {
+                    format!(". {};\n", sh_quoted(script))
+                }
+                Dialect::Fish => format!("source {};\n", fish_quoted(script)),
+                Dialect::Csh => format!("source {};\n", csh_quoted(script)),
+            }
+        }
+        // csh has no functions, and sourced scripts cannot be undone
+        _ => return None,
+    };
+
+    Some(statement)
+}
+
+/// Shell statements in `dialect` applying `operations` to a shell whose
+/// environment is `vars`, or undoing them if `deactivate` is set.
+///
+/// Values replaced when activating are saved in `SCCMOD_SAVED_*` variables,
+/// so that deactivating restores them. Scripts are only sourced by the shell
+/// they were written for, and csh gets no shell functions.
+#[must_use]
+pub fn script<S: BuildHasher + Clone>(
+    operations: &[(String, Environment)],
+    vars: &HashMap<String, String, S>,
+    dialect: Dialect,
+    deactivate: bool,
+) -> String {
+    let mut after = vars.clone();
+    let mut touched: Vec<String> = Vec::new();
+    let mut statements: Vec<String> = Vec::new();
+
+    let mut ordered: Vec<&(String, Environment)> = operations.iter().collect();
+    if deactivate {
+        ordered.reverse();
+    }
+
+    for (key, value) in ordered {
+        if matches!(
+            value,
+            Environment::Set(_) | Environment::SetExact(_) | Environment::Unset
+        ) {
+            let saved = format!("{SAVED_PREFIX}{key}");
+
+            if let (Some(old), false) = (vars.get(key), deactivate) {
+                after.entry(saved.clone()).or_insert_with(|| old.clone());
+            }
+
+            if !touched.contains(&saved) {
+                touched.push(saved);
+            }
+        }
+
+        if !touched.contains(key) {
+            touched.push(key.clone());
+        }
+
+        if deactivate {
+            revert(&mut after, key, value);
+        } else {
+            apply(&mut after, key, value);
+        }
+
+        statements.extend(statement(dialect, key, value, deactivate));
+    }
+
+    touched
+        .iter()
+        .filter_map(|key| match (vars.get(key), after.get(key)) {
+            (old, Some(new)) if old != Some(new) => {
+                Some(export(dialect, key, new))
+            }
+            (Some(_), None) => Some(unset(dialect, key)),
+            _ => None,
+        })
+        .chain(statements)
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    fn set(key: &str, value: &str) -> (String, Environment) {
+        (key.to_string(), Environment::SetExact(value.to_string()))
+    }
+
+    #[test]
+    fn dialects() {
+        assert_eq!(dialect("bash"), Ok(Dialect::Bash));
+        assert_eq!(dialect("sh"), Ok(Dialect::Bash));
+        assert_eq!(dialect("/bin/zsh"), Ok(Dialect::Zsh));
+        assert_eq!(dialect("/usr/bin/fish"), Ok(Dialect::Fish));
+        assert_eq!(dialect("/bin/tcsh"), Ok(Dialect::Csh));
+        assert!(dialect("pwsh").is_err());
+        assert!(dialect("").is_err());
+    }
+
+    #[test]
+    fn absolute_paths_are_kept() {
+        let source = |script: &str| match absolute(
+            "/opt/tool/1",
+            Environment::SourceSh(script.into()),
+        ) {
+            Environment::SourceSh(script) => script,
+            other => panic!("unexpected {other:?}"),
+        };
+
+        assert_eq!(source("/etc/profile.d/tool.sh"), "/etc/profile.d/tool.sh");
+        assert_eq!(source("share/env.sh"), "/opt/tool/1/share/env.sh");
+    }
+
+    #[test]
+    fn sh_quoting() {
+        assert_eq!(sh_quoted(""), "''");
+        assert_eq!(sh_quoted("a'b $c\nd"), "'a'\\''b $c\nd'");
+    }
+
+    #[test]
+    fn fish_quoting() {
+        assert_eq!(fish_quoted(""), "''");
+        assert_eq!(fish_quoted("a'b $c\nd\\e"), "'a\\'b $c\nd\\\\e'");
+    }
+
+    #[test]
+    fn csh_quoting() {
+        assert_eq!(csh_quoted(""), "''");
+        assert_eq!(csh_quoted("a'b $c\nd!"), "'a'\\''b $c\\\nd\\!'");
+    }
+
+    #[test]
+    fn exports_are_quoted_for_each_dialect() {
+        let operations = [set("GREETING", "it's $HOME\nok")];
+        let script = |dialect| script(&operations, &vars(&[]), dialect, false);
+
+        assert_eq!(
+            script(Dialect::Bash),
+            "export GREETING='it'\\''s $HOME\nok';\n"
+        );
+        assert_eq!(script(Dialect::Zsh), script(Dialect::Bash));
+        assert_eq!(
+            script(Dialect::Fish),
+            "set -gx GREETING 'it\\'s $HOME\nok';\n"
+        );
+        assert_eq!(
+            script(Dialect::Csh),
+            "setenv GREETING 'it'\\''s $HOME\\\nok';\n"
+        );
+    }
+
+    #[test]
+    fn fish_paths_are_lists() {
+        let operations = [(
+            "PATH".to_string(),
+            Environment::Prepend("/opt/a b/bin".to_string(), None),
+        )];
+
+        assert_eq!(
+            script(
+                &operations,
+                &vars(&[("PATH", "/usr/bin")]),
+                Dialect::Fish,
+                false
+            ),
+            "set -gx PATH '/opt/a b/bin' '/usr/bin';\n"
+        );
+    }
+
+    #[test]
+    fn replaced_values_are_saved_and_restored() {
+        let operations = [set("CC", "clang")];
+
+        assert_eq!(
+            script(&operations, &vars(&[("CC", "gcc")]), Dialect::Bash, false),
+            "export SCCMOD_SAVED_CC='gcc';\nexport CC='clang';\n"
+        );
+        assert_eq!(
+            script(
+                &operations,
+                &vars(&[("CC", "clang"), ("SCCMOD_SAVED_CC", "gcc")]),
+                Dialect::Bash,
+                true
+            ),
+            "unset SCCMOD_SAVED_CC;\nexport CC='gcc';\n"
+        );
+    }
+
+    #[test]
+    fn new_values_are_unset_on_deactivation() {
+        let operations = [set("CC", "clang")];
+
+        assert_eq!(
+            script(&operations, &vars(&[]), Dialect::Bash, false),
+            "export CC='clang';\n"
+        );
+        assert_eq!(
+            script(&operations, &vars(&[("CC", "clang")]), Dialect::Csh, true),
+            "unsetenv CC;\n"
+        );
+    }
+
+    #[test]
+    fn unset_variables_are_restored() {
+        let operations = [("OLD".to_string(), Environment::Unset)];
+
+        assert_eq!(
+            script(&operations, &vars(&[("OLD", "x")]), Dialect::Fish, false),
+            "set -gx SCCMOD_SAVED_OLD 'x';\nset -e OLD;\n"
+        );
+        assert_eq!(
+            script(
+                &operations,
+                &vars(&[("SCCMOD_SAVED_OLD", "x")]),
+                Dialect::Fish,
+                true
+            ),
+            "set -e SCCMOD_SAVED_OLD;\nset -gx OLD 'x';\n"
+        );
+    }
+
+    #[test]
+    fn path_entries_are_moved_and_removed() {
+        let mut env = vars(&[("PATH", "/a:/b:/c")]);
+
+        apply(&mut env, "PATH", &Environment::Append("/a".to_string(), None));
+        assert_eq!(env["PATH"], "/b:/c:/a");
+
+        apply(&mut env, "PATH", &Environment::Prepend("/c".to_string(), None));
+        assert_eq!(env["PATH"], "/c:/b:/a");
+
+        revert(&mut env, "PATH", &Environment::Prepend("/c".to_string(), None));
+        assert_eq!(env["PATH"], "/b:/a");
+
+        apply(
+            &mut env,
+            "PATH",
+            &Environment::RemovePath("/b".to_string(), None),
+        );
+        apply(
+            &mut env,
+            "PATH",
+            &Environment::RemovePath("/a".to_string(), None),
+        );
+        assert!(!env.contains_key("PATH"));
+    }
+
+    #[test]
+    fn custom_separators() {
+        let mut env = vars(&[("LIST", "a;b")]);
+        let sep = Some(";".to_string());
+
+        apply(
+            &mut env,
+            "LIST",
+            &Environment::Append("c".to_string(), sep.clone()),
+        );
+        apply(&mut env, "LIST", &Environment::RemovePath("a".to_string(), sep));
+        assert_eq!(env["LIST"], "b;c");
+    }
+
+    #[test]
+    fn functions_are_skipped_by_csh() {
+        let operations = [(
+            "greet".to_string(),
+            Environment::SetFunction("echo hi".to_string()),
+        )];
+        let script = |dialect, deactivate| {
+            script(&operations, &vars(&[]), dialect, deactivate)
+        };
+
+        assert_eq!(script(Dialect::Bash, false), "greet() { echo hi; };\n");
+        assert_eq!(script(Dialect::Bash, true), "unset -f greet;\n");
+        assert_eq!(
+            script(Dialect::Fish, false),
+            "function greet; echo hi; end;\n"
+        );
+        assert_eq!(script(Dialect::Fish, true), "functions -e greet;\n");
+        assert_eq!(script(Dialect::Csh, false), "");
+    }
+
+    #[test]
+    fn aliases_are_quoted() {
+        let operations = [(
+            "hi".to_string(),
+            Environment::SetAlias("echo 'hi'!".to_string()),
+        )];
+        let script = |dialect| script(&operations, &vars(&[]), dialect, false);
+
+        assert_eq!(script(Dialect::Bash), "alias hi='echo '\\''hi'\\''!';\n");
+        assert_eq!(script(Dialect::Fish), "alias hi 'echo \\'hi\\'!';\n");
+        assert_eq!(script(Dialect::Csh), "alias hi 'echo '\\''hi'\\''\\!';\n");
+    }
+
+    #[test]
+    fn scripts_are_sourced_by_matching_shells() {
+        let operations = [(
+            "sh".to_string(),
+            Environment::SourceSh("/p/env.sh".to_string()),
+        )];
+        let script = |dialect, deactivate| {
+            script(&operations, &vars(&[]), dialect, deactivate)
+        };
+
+        assert_eq!(script(Dialect::Bash, false), ". '/p/env.sh';\n");
+        assert_eq!(script(Dialect::Zsh, false), ". '/p/env.sh';\n");
+        assert_eq!(script(Dialect::Fish, false), "");
+        assert_eq!(script(Dialect::Csh, false), "");
+        assert_eq!(script(Dialect::Bash, true), "");
+    }
+}
@@ -5,7 +5,10 @@ use pyo3::{
     Bound, PyAny,
 };
 
-use crate::builders::{cmake::CMake, make::Make};
+use crate::{
+    builders::{cmake::CMake, make::Make},
+    hash::canonical,
+};
 
 pub trait BuilderImpl: Sized + Clone {
     /// Generate a builder object from a python object.
@@ -18,6 +21,11 @@ pub trait BuilderImpl: Sized + Clone {
     /// provided object is invalid.
     fn from_py(object: &Bound<PyAny>) -> Result<Self, String>;
 
+    /// Serialise the settings which affect what is built, with
+    /// [`crate::hash::canonical`], for the fingerprint of a module. Changing
+    /// the result changes the revision of every module using this builder.
+    fn fingerprint(&self) -> String;
+
     /// Perform the build operation specified by the struct.
     ///
     /// For example, if this is a [`CMake`] instance, `cmake` is run on the
@@ -83,6 +91,15 @@ impl BuilderImpl for Builder {
         }
     }
 
+    fn fingerprint(&self) -> String {
+        let (kind, settings) = match self {
+            Self::CMake(cmake) => ("cmake", cmake.fingerprint()),
+            Self::Make(make) => ("make", make.fingerprint()),
+        };
+
+        canonical(&[("builder", kind.to_string()), ("settings", settings)])
+    }
+
     fn build<
         P0: AsRef<Path> + Debug,
         P1: AsRef<Path> + Debug,
@@ -3,7 +3,11 @@ use std::{fs, path, path::Path};
 use pyo3::{prelude::PyAnyMethods, Bound, PyAny};
 
 use crate::{
-    builders::builder_trait::BuilderImpl, config, file_manager::PATH_SEP, log,
+    builders::builder_trait::BuilderImpl,
+    config,
+    file_manager::PATH_SEP,
+    hash::{canonical, canonical_list, canonical_option},
+    log,
     shell::Shell,
 };
 
@@ -193,6 +197,23 @@ impl BuilderImpl for CMake {
         Ok(Self { build_type, jobs, prefix_args, configure_flags, cmake_root })
     }
 
+    fn fingerprint(&self) -> String {
+        let build_type = match self.build_type {
+            CMakeBuildType::Debug => "debug",
+            CMakeBuildType::Release => "release",
+            CMakeBuildType::RelWithDebInfo => "relwithdebinfo",
+            CMakeBuildType::MinSizeRel => "minsizerel",
+        };
+
+        // The number of jobs does not affect the result
+        canonical(&[
+            ("build_type", build_type.to_string()),
+            ("prefix_args", canonical_list(self.prefix_args.as_ref())),
+            ("configure_flags", canonical_list(self.configure_flags.as_ref())),
+            ("cmake_root", canonical_option(self.cmake_root.as_ref())),
+        ])
+    }
+
     fn build<
         P0: AsRef<Path> + std::fmt::Debug,
         P1: AsRef<Path> + std::fmt::Debug,
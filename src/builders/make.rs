@@ -3,8 +3,13 @@ use std::{fs, path, path::Path, process::Command};
 use pyo3::{prelude::PyAnyMethods, Bound, PyAny};
 
 use crate::{
-    builders::builder_trait::BuilderImpl, cli::child_logger, config,
-    file_manager::PATH_SEP, log, shell::Shell,
+    builders::builder_trait::BuilderImpl,
+    cli::child_logger,
+    config,
+    file_manager::PATH_SEP,
+    hash::{canonical, canonical_list, canonical_option},
+    log,
+    shell::Shell,
 };
 
 #[derive(Debug, Clone)]
@@ -181,6 +186,16 @@ impl BuilderImpl for Make {
         Ok(Self { configure, jobs, prefix_args, configure_flags, make_root })
     }
 
+    fn fingerprint(&self) -> String {
+        // The number of jobs does not affect the result
+        canonical(&[
+            ("configure", self.configure.to_string()),
+            ("prefix_args", canonical_list(self.prefix_args.as_ref())),
+            ("configure_flags", canonical_list(self.configure_flags.as_ref())),
+            ("make_root", canonical_option(self.make_root.as_ref())),
+        ])
+    }
+
     fn build<
         P0: AsRef<Path> + std::fmt::Debug,
         P1: AsRef<Path> + std::fmt::Debug,
@@ -1,11 +1,15 @@
-use std::io::Write;
+use std::{
+    collections::HashMap,
+    io::{IsTerminal, Write},
+};
 
 use colored::Colorize;
 
 use crate::{
-    config, log,
+    activate, cli, config, database, defaults, dependencies, diff, disk,
+    file_manager, flavours, log,
     module::{self, get_modules, Module},
-    module_resolver,
+    module_resolver, modulefile, status,
 };
 
 /// Internal boilerplate handler which, given a set of partials and a function,
@@ -16,7 +20,7 @@ use crate::{
 /// return an error.
 pub fn resolver_boilerplate(
     partials: &[&str],
-    func: fn(&Module) -> Result<(), String>,
+    func: impl Fn(&Module) -> Result<(), String>,
 ) -> Result<(), String> {
     match module_resolver::resolve(partials)? {
         module_resolver::ResolveMatch::Full(m) => func(&m),
@@ -116,7 +120,583 @@ pub fn resolver_boilerplate(
     }
 }
 
-pub fn info(config: &config::Config) -> Result<(), String> {
+/// Ask the user a yes/no question, returning `true` if they answer yes. If
+/// `assume_yes` is set, the question is skipped and `true` is returned.
+///
+/// # Errors
+/// Errors if stdin is not a terminal and `assume_yes` is not set, or if
+/// stdout cannot be flushed.
+pub fn confirm(question: &str, assume_yes: bool) -> Result<bool, String> {
+    if assume_yes {
+        return Ok(true);
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return Err(format!(
+            "Cannot ask '{question}' as stdin is not a terminal. Use --yes \
+to proceed without confirmation"
+        ));
+    }
+
+    print!("{}", format!("{question} [y/N]: ").yellow().bold());
+    std::io::stdout().flush().map_err(|e| e.to_string())?;
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        log::warn("Failed to read input");
+        return Ok(false);
+    }
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Install `targets`, along with any prerequisites which are not yet
+/// installed, in dependency order. The plan is shown before anything is
+/// built.
+///
+/// # Errors
+/// Errors if the dependency graph cannot be resolved or if any installation
+/// fails.
+pub fn install_with_dependencies(
+    targets: &[Module],
+    modules: &[Module],
+    selection: &flavours::Selection,
+    config: &config::Config,
+) -> Result<(), String> {
+    let order = dependencies::resolve(targets, modules, selection)?;
+
+    println!("{}", "Installation plan:".bold().purple());
+
+    let mut plan = Vec::new();
+    for module in &order {
+        let is_target =
+            targets.iter().any(|t| t.identifier() == module.identifier());
+
+        if is_target
+            || !dependencies::is_installed(module, modules, selection, config)?
+        {
+            println!(
+                "  {} {}",
+                format!("{}.", plan.len() + 1).bold(),
+                module.identifier().bold().cyan()
+            );
+            plan.push(module);
+        } else {
+            println!(
+                "  {} {} {}",
+                "-".bold(),
+                module.identifier().cyan(),
+                "(already installed)".italic()
+            );
+        }
+    }
+
+    // Installing removes nothing, so unattended installs proceed unasked
+    let unattended = !std::io::stdin().is_terminal();
+
+    if !confirm("Proceed with installation?", unattended)? {
+        log::warn("Installation cancelled");
+        return Ok(());
+    }
+
+    for module in plan {
+        module::install(module, modules, selection, config)?;
+    }
+
+    Ok(())
+}
+
+/// Print a list of `paths` along with their sizes, returning the total size.
+fn print_paths(paths: &[String]) -> u64 {
+    let mut total = 0;
+
+    for path in paths {
+        let size = file_manager::dir_size(path);
+        total += size;
+
+        println!(
+            "  {} {} {}",
+            "-".bold(),
+            path.cyan(),
+            format!("({})", file_manager::format_size(size)).italic()
+        );
+    }
+
+    total
+}
+
+/// What uninstalling some flavours of a module removes
+#[derive(Debug, Clone, Default)]
+pub struct Removal {
+    /// Install directories, which may not all exist
+    pub paths: Vec<String>,
+
+    /// Install records of the removed flavours
+    pub records: Vec<database::InstallRecord>,
+
+    /// Modulefiles, which may not all exist
+    pub modulefiles: Vec<String>,
+}
+
+/// Select what uninstalling `flavs` of `module` removes, given its install
+/// `records`, its installed `revisions` and the modulefiles it generates for
+/// every flavour.
+///
+/// Every revision of each flavour is removed, including those installed
+/// without being recorded. A modulefile covering every flavour is only
+/// removed once no other flavours remain recorded.
+#[must_use]
+pub fn uninstall_removal(
+    module: &Module,
+    flavs: &[(Vec<Module>, usize)],
+    records: &[database::InstallRecord],
+    revisions: &[usize],
+    outputs: Vec<modulefile::Output>,
+) -> Removal {
+    let names: Vec<String> = flavs.iter().map(flavours::gen_name).collect();
+
+    let (targets, remaining): (Vec<_>, Vec<_>) = records
+        .iter()
+        .filter(|rec| rec.is_for(module))
+        .cloned()
+        .partition(|rec| names.contains(&rec.flavour));
+
+    let mut paths: Vec<String> =
+        targets.iter().map(|rec| rec.install_path.clone()).collect();
+
+    for &revision in revisions {
+        let module = Module { revision, ..module.clone() };
+
+        for flav in flavs {
+            let (_, _, install_path, _) = module.parse(&(&flav.0, flav.1));
+            paths.push(install_path);
+        }
+    }
+
+    paths.sort();
+    paths.dedup();
+
+    let modulefiles = outputs
+        .into_iter()
+        .filter(|output| {
+            output
+                .flavour
+                .as_ref()
+                .map_or(remaining.is_empty(), |name| names.contains(name))
+        })
+        .map(|output| output.path)
+        .collect();
+
+    Removal { paths, records: targets, modulefiles }
+}
+
+/// Uninstall the selected flavours of `module`, removing their install
+/// directories, install records and modulefiles. A modulefile covering every
+/// flavour is removed once no flavours remain installed.
+///
+/// # Errors
+/// Errors if other installed modules depend on `module` and `force` is not
+/// set, if the uninstall is not confirmed and `yes` is not set, or if
+/// anything cannot be removed.
+pub fn uninstall(
+    module: &Module,
+    selection: &flavours::Selection,
+    force: bool,
+    yes: bool,
+    config: &config::Config,
+) -> Result<(), String> {
+    let modules = get_modules()?;
+    let records = database::records(config)?;
+
+    let flavs = selection.filter(flavours::generate_with(module, &modules)?);
+    let revisions: Vec<usize> =
+        module.installed_revisions().into_iter().map(|(rev, _)| rev).collect();
+
+    // Include modulefiles written with --force
+    let Removal { mut paths, records: targets, mut modulefiles } =
+        uninstall_removal(
+            module,
+            &flavs,
+            &records,
+            &revisions,
+            modulefile::outputs(module, &modules, config, true)?,
+        );
+
+    paths.retain(|path| std::path::Path::new(path).exists());
+    modulefiles.retain(|path| std::path::Path::new(path).exists());
+
+    if paths.is_empty() && targets.is_empty() && modulefiles.is_empty() {
+        log::warn(&format!("'{}' is not installed", module.identifier()));
+        return Ok(());
+    }
+
+    let dependents =
+        dependencies::installed_dependents(module, &modules, &records);
+
+    if !dependents.is_empty() {
+        let msg = format!(
+            "Installed modules depend on '{}': {}",
+            module.identifier(),
+            dependents.join(", ")
+        );
+
+        if !force {
+            return Err(format!("{msg}. Use --force to uninstall anyway"));
+        }
+
+        log::warn(&msg);
+    }
+
+    println!(
+        "{} {}",
+        "Uninstalling".bold().purple(),
+        module.identifier().bold().cyan()
+    );
+
+    let total = print_paths(&paths);
+
+    for modulefile in &modulefiles {
+        println!("  {} {}", "-".bold(), modulefile.cyan());
+    }
+
+    println!("Total: {}", file_manager::format_size(total).bold());
+
+    if !confirm("Proceed with uninstall?", yes)? {
+        log::warn("Uninstall cancelled");
+        return Ok(());
+    }
+
+    for path in &paths {
+        file_manager::remove_path(path)?;
+
+        // Remove the revision directory once its last flavour is gone
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            let _ = std::fs::remove_dir(parent);
+        }
+    }
+
+    for record in targets {
+        record.remove(config)?;
+    }
+
+    for modulefile in &modulefiles {
+        file_manager::remove_path(modulefile)?;
+    }
+
+    log::status(&format!("Uninstalled '{}'", module.identifier()));
+
+    Ok(())
+}
+
+/// Remove the build directories of `module`, and its downloaded sources if
+/// `sources` is set. If `dry_run` is set, only list what would be removed.
+///
+/// # Errors
+/// Errors if anything cannot be removed.
+pub fn clean(
+    module: &Module,
+    sources: bool,
+    dry_run: bool,
+) -> Result<(), String> {
+    // The build directory is inside the source directory
+    let path = if sources { &module.source_path } else { &module.build_path };
+
+    if !std::path::Path::new(path).exists() {
+        log::info(&format!("Nothing to clean for '{}'", module.identifier()));
+        return Ok(());
+    }
+
+    println!(
+        "{} {}",
+        (if dry_run { "Would clean" } else { "Cleaning" }).bold().purple(),
+        module.identifier().bold().cyan()
+    );
+
+    print_paths(std::slice::from_ref(path));
+
+    if dry_run {
+        return Ok(());
+    }
+
+    file_manager::remove_path(path)
+}
+
+/// Print the disk space used by `modules`, per module, flavour and phase.
+///
+/// # Errors
+/// Errors if the flavours of a module cannot be generated.
+pub fn print_disk_usage(modules: &[Module]) -> Result<(), String> {
+    let available = get_modules()?;
+    let size = |bytes: u64| {
+        if bytes == 0 {
+            "-".to_string()
+        } else {
+            file_manager::format_size(bytes)
+        }
+    };
+
+    let mut rows = Vec::new();
+    let mut total = 0;
+
+    for m in modules {
+        let usage = disk::usage(m, &available)?;
+
+        if usage.first().map_or(0, disk::Usage::total) == 0 {
+            continue;
+        }
+
+        for entry in usage {
+            if entry.flavour.is_none() {
+                total += entry.total();
+            } else if entry.total() == 0 {
+                continue;
+            }
+
+            rows.push(vec![
+                entry.identifier.clone(),
+                entry.flavour.clone().unwrap_or_else(|| "(all)".to_string()),
+                size(entry.source),
+                size(entry.build),
+                size(entry.install),
+                size(entry.total()),
+            ]);
+        }
+    }
+
+    cli::print_table(
+        &["Module", "Flavour", "Source", "Build", "Install", "Total"],
+        &rows,
+    );
+
+    println!("Total: {}", file_manager::format_size(total).bold());
+
+    Ok(())
+}
+
+/// Remove orphaned trees, old revisions and stale build directories.
+///
+/// Build directories are stale once unmodified for `days` days. Nothing is
+/// removed without confirmation, unless `yes` is set. Install records whose
+/// install directory no longer exists are removed as well.
+///
+/// # Errors
+/// Errors if `days` is too large, if the modules cannot be listed, if the
+/// removal is not confirmed and `yes` is not set, or if anything cannot be
+/// removed.
+pub fn collect_garbage(
+    days: u64,
+    dry_run: bool,
+    yes: bool,
+    config: &config::Config,
+) -> Result<(), String> {
+    let max_age =
+        days.checked_mul(86400)
+            .map(std::time::Duration::from_secs)
+            .ok_or_else(|| format!("Number of days '{days}' is too large"))?;
+
+    let modules = get_modules()?;
+    let records = database::records(config)?;
+    let garbage = disk::garbage(&modules, &records, config, max_age)?;
+
+    if garbage.is_empty() {
+        log::info("Nothing to collect");
+    } else {
+        let rows: Vec<Vec<String>> = garbage
+            .iter()
+            .map(|g| {
+                vec![
+                    g.path.display().to_string(),
+                    file_manager::format_size(file_manager::dir_size(&g.path)),
+                    g.reason.clone(),
+                ]
+            })
+            .collect();
+
+        cli::print_table(&["Path", "Size", "Reason"], &rows);
+
+        let total: u64 =
+            garbage.iter().map(|g| file_manager::dir_size(&g.path)).sum();
+        println!("Total: {}", file_manager::format_size(total).bold());
+
+        if dry_run {
+            return Ok(());
+        }
+
+        if !confirm("Remove these paths?", yes)? {
+            log::warn("Garbage collection cancelled");
+            return Ok(());
+        }
+
+        for g in &garbage {
+            file_manager::remove_path(&g.path)?;
+
+            // Tidy up the parent directory if it is now empty
+            if let Some(parent) = g.path.parent() {
+                let _ = std::fs::remove_dir(parent);
+            }
+        }
+    }
+
+    if !dry_run {
+        for record in records {
+            if !std::path::Path::new(&record.install_path).exists() {
+                log::info(&format!(
+                    "Removing install record of '{}' {}",
+                    record.identifier, record.flavour
+                ));
+                record.remove(config)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Create the flavour selection from the `--flavour` flag and the
+/// `default_flavours` in the config.
+///
+/// # Errors
+/// Errors if the modules cannot be listed or the selection is invalid.
+pub fn flavour_selection(
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<flavours::Selection, String> {
+    flavours::Selection::new(config, flags.value("flavour"), &get_modules()?)
+}
+
+/// Escape a string for use as a JSON string literal, including the quotes
+fn json_string(value: &str) -> String {
+    let mut result = String::from('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => {
+                let code = format!("\\u{:04x}", c as u32);
+                result.push_str(&code);
+            }
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+/// Print the flavour matrix of `module`: every permutation, whether it will be
+/// built (and if not, why), its paths and whether it is installed.
+///
+/// # Errors
+/// Errors if the modules cannot be listed or the flavours cannot be generated.
+pub fn print_flavours(
+    module: &Module,
+    selection: &flavours::Selection,
+    json: bool,
+) -> Result<(), String> {
+    struct Row {
+        name: String,
+        dropped_by: Option<String>,
+        selected: bool,
+        installed: bool,
+        build_path: String,
+        install_path: String,
+    }
+
+    let modules = get_modules()?;
+    let records = database::records_for(module, &config::read()?)?;
+
+    let rows: Vec<Row> = flavours::evaluate(module, &modules)?
+        .into_iter()
+        .map(|perm| {
+            let (_, build_path, install_path, _) =
+                module.parse(&(&perm.flavour.0, perm.flavour.1));
+
+            Row {
+                name: flavours::gen_name(&perm.flavour),
+                selected: selection.matches(&perm.flavour),
+                dropped_by: perm.dropped_by,
+                installed: database::is_installed(
+                    &records,
+                    module,
+                    &perm.flavour,
+                ),
+                build_path,
+                install_path,
+            }
+        })
+        .collect();
+
+    if json {
+        let entries: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                format!(
+                    "    {{ \"flavour\": {}, \"dropped_by\": {}, \"selected\": {}, \
+\"installed\": {}, \"build_path\": {}, \"install_path\": {} }}",
+                    json_string(&row.name),
+                    row.dropped_by.as_deref().map_or_else(
+                        || "null".to_string(),
+                        json_string
+                    ),
+                    row.selected,
+                    row.installed,
+                    json_string(&row.build_path),
+                    json_string(&row.install_path),
+                )
+            })
+            .collect();
+
+        println!(
+            "{{\n  \"module\": {},\n  \"flavours\": [\n{}\n  ]\n}}",
+            json_string(&module.identifier()),
+            entries.join(",\n")
+        );
+
+        return Ok(());
+    }
+
+    let table: Vec<Vec<String>> = rows
+        .into_iter()
+        .map(|row| {
+            let status = match row.dropped_by {
+                Some(rule) => format!("dropped by {rule}"),
+                None if !row.selected => "not selected".to_string(),
+                None => "build".to_string(),
+            };
+
+            let installed = if row.installed { "yes" } else { "no" };
+
+            vec![
+                row.name,
+                status,
+                installed.to_string(),
+                row.build_path,
+                row.install_path,
+            ]
+        })
+        .collect();
+
+    println!(
+        "{} {}",
+        "Flavours of".bold().purple(),
+        module.identifier().bold().cyan()
+    );
+
+    cli::print_table(
+        &["Flavour", "Status", "Installed", "Build path", "Install path"],
+        &table,
+    );
+
+    Ok(())
+}
+
+pub fn info(
+    _flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
     fn fmt<T: std::fmt::Debug>(name: &str, value: &T) {
         // println!("{}", format!("{name} {value:?}").bold().purple());
         println!("{} {}", name.bold().purple(), format!("{value:?}").cyan());
@@ -129,6 +709,8 @@ pub fn info(config: &config::Config) -> Result<(), String> {
     fmt("install_root  . . . :", &config.install_root);
     fmt("shell . . . . . . . :", &config.shell);
     fmt("num_threads . . . . :", &config.num_threads);
+    fmt("default_flavours  . :", &config.default_flavours);
+    fmt("modulefile_format . :", &config.modulefile_format);
 
     Ok(())
 }
@@ -138,11 +720,28 @@ pub fn info(config: &config::Config) -> Result<(), String> {
 /// # Errors
 ///
 /// This function will error if an invalid modulefile is found.
-pub fn list_callback(_config: &config::Config) -> Result<(), String> {
+pub fn list_callback(
+    _flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
     println!("{}", "Available Modules:".bold().purple());
 
+    let records = database::records(config)?;
+
     for p in &get_modules()? {
-        println!(" > {}", p.identifier().bold().cyan());
+        let installed = records
+            .iter()
+            .filter(|rec| rec.is_for(p) && rec.revision == p.revision)
+            .count();
+
+        if installed == 0 {
+            println!(" > {}", p.identifier().bold().cyan());
+        } else {
+            println!(
+                " > {} ({installed} flavour(s) installed)",
+                p.identifier().bold().cyan()
+            );
+        }
     }
 
     Ok(())
@@ -156,6 +755,7 @@ pub fn list_callback(_config: &config::Config) -> Result<(), String> {
 /// or if the call to [`Module.download`] fails.
 pub fn download_module(
     partials: &[&str],
+    _flags: &cli::Flags,
     _config: &config::Config,
 ) -> Result<(), String> {
     resolver_boilerplate(partials, module::download)
@@ -166,7 +766,10 @@ pub fn download_module(
 /// # Errors
 ///
 /// Errors if the modules cannot be listed or if any module fails to download
-pub fn download_all(_config: &config::Config) -> Result<(), String> {
+pub fn download_all(
+    _flags: &cli::Flags,
+    _config: &config::Config,
+) -> Result<(), String> {
     for m in &get_modules()? {
         module::download(m)?;
     }
@@ -182,9 +785,12 @@ pub fn download_all(_config: &config::Config) -> Result<(), String> {
 /// or if the call to [`Module.build`] fails.
 pub fn build_module(
     partials: &[&str],
-    _config: &config::Config,
+    flags: &cli::Flags,
+    config: &config::Config,
 ) -> Result<(), String> {
-    resolver_boilerplate(partials, module::build)
+    let selection = flavour_selection(flags, config)?;
+    let modules = get_modules()?;
+    resolver_boilerplate(partials, |m| module::build(m, &modules, &selection))
 }
 
 /// A callback function to build all available modules
@@ -192,9 +798,16 @@ pub fn build_module(
 /// # Errors
 ///
 /// Errors if the modules cannot be listed or if any module fails to build
-pub fn build_all(_config: &config::Config) -> Result<(), String> {
-    for m in &get_modules()? {
-        module::build(m)?;
+pub fn build_all(
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let selection = flavour_selection(flags, config)?;
+
+    let modules = get_modules()?;
+
+    for m in &modules {
+        module::build(m, &modules, &selection)?;
     }
 
     Ok(())
@@ -208,16 +821,506 @@ pub fn build_all(_config: &config::Config) -> Result<(), String> {
 /// specified name, or if the call to [`Module.install`] fails.
 pub fn install_module(
     partials: &[&str],
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let selection = flavour_selection(flags, config)?;
+    let modules = get_modules()?;
+
+    if flags.is_set("with-deps") {
+        resolver_boilerplate(partials, |m| {
+            install_with_dependencies(
+                std::slice::from_ref(m),
+                &modules,
+                &selection,
+                config,
+            )
+        })
+    } else {
+        resolver_boilerplate(partials, |m| {
+            module::install(m, &modules, &selection, config)
+        })
+    }
+}
+
+/// A callback function to print the flavour matrix of a module.
+///
+/// # Errors
+///
+/// Errors if a single module cannot be resolved from the specified name,
+/// or if its flavours cannot be generated.
+pub fn flavours_module(
+    partials: &[&str],
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let selection = flavour_selection(flags, config)?;
+    let json = flags.is_set("json");
+
+    resolver_boilerplate(partials, |m| print_flavours(m, &selection, json))
+}
+
+/// A callback function to print the shell statements activating a module and
+/// its dependencies, or deactivating them with `--deactivate`, for use with
+/// `eval "$(sccmod env <module>)"`.
+///
+/// # Errors
+///
+/// Errors if a single module cannot be resolved from the specified name, the
+/// shell is not supported, or the environment of the module cannot be
+/// resolved.
+pub fn env_module(
+    partials: &[&str],
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let selection = flavour_selection(flags, config)?;
+    let dialect = flags
+        .value("shell")
+        .map_or_else(|| Ok(activate::login_shell()), activate::dialect)?;
+    let deactivate = flags.is_set("deactivate");
+
+    let vars: HashMap<String, String> = std::env::vars().collect();
+
+    resolver_boilerplate(partials, |m| {
+        let operations = activate::resolve(m, &selection, config)?;
+        print!("{}", activate::script(&operations, &vars, dialect, deactivate));
+        Ok(())
+    })
+}
+
+/// A callback function to run the command given after `--` in the
+/// environment of a module and its dependencies, replacing sccmod.
+///
+/// Scripts sourced by the module, aliases and shell functions are not
+/// applied.
+///
+/// # Errors
+///
+/// Errors if no command is given, a single module cannot be resolved from the
+/// specified name, its environment cannot be resolved or the command cannot
+/// be run.
+pub fn exec_module(
+    partials: &[&str],
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let Some((program, args)) = flags.trailing().split_first() else {
+        return Err(
+            "No command given. Usage: sccmod exec <module> -- <command>"
+                .to_string(),
+        );
+    };
+
+    let selection = flavour_selection(flags, config)?;
+
+    resolver_boilerplate(partials, |m| {
+        let mut vars: HashMap<String, String> = std::env::vars().collect();
+        for (key, value) in activate::resolve(m, &selection, config)? {
+            activate::apply(&mut vars, &key, &value);
+        }
+
+        replace_process(program, args, &vars)
+    })
+}
+
+/// Replace sccmod with `program`, run with `args` in the environment `vars`.
+/// Only returns if the program could not be started.
+#[cfg(unix)]
+fn replace_process(
+    program: &str,
+    args: &[String],
+    vars: &HashMap<String, String>,
+) -> Result<(), String> {
+    use std::os::unix::process::CommandExt;
+
+    let err = std::process::Command::new(program)
+        .args(args)
+        .env_clear()
+        .envs(vars)
+        .exec();
+
+    Err(format!("Failed to run '{program}': {err}"))
+}
+
+/// Replacing the process is only supported on Unix
+#[cfg(not(unix))]
+fn replace_process(
+    program: &str,
+    _args: &[String],
+    _vars: &HashMap<String, String>,
+) -> Result<(), String> {
+    Err(format!(
+        "Cannot run '{program}': `sccmod exec` is only supported on Unix"
+    ))
+}
+
+/// A callback function for a command given to `exec` without a module.
+///
+/// # Errors
+///
+/// Always errors, as the module to run the command in is missing.
+pub fn exec_without_module(
+    command: &[&str],
+    _flags: &cli::Flags,
     _config: &config::Config,
 ) -> Result<(), String> {
-    resolver_boilerplate(partials, module::install)
+    Err(format!("No module given to run '{}' in", command.join(" ")))
 }
 
-pub fn write_modulefile(
+/// Print the download, build and install state of every flavour of `modules`.
+///
+/// # Errors
+/// Errors if the install database cannot be read or the status of a module
+/// cannot be determined.
+pub fn print_status(
+    modules: &[Module],
+    config: &config::Config,
+) -> Result<(), String> {
+    let available = get_modules()?;
+    let records = database::records(config)?;
+
+    let mut rows = Vec::new();
+
+    for m in modules {
+        for status in status::collect(m, &available, &records, config)? {
+            // Abbreviate commit hashes, but not URLs
+            let source = match status.downloaded.as_deref() {
+                Some(rev) if rev.chars().all(|c| c.is_ascii_hexdigit()) => {
+                    rev.chars().take(12).collect()
+                }
+                Some(rev) => rev.to_string(),
+                None => "-".to_string(),
+            };
+
+            let installed = match &status.installed {
+                Some(rec) if status.is_current(m) => {
+                    format!("revision {}", rec.revision)
+                }
+                Some(rec) => format!("revision {} (old)", rec.revision),
+                None => "no".to_string(),
+            };
+
+            let modulefile = match status.modulefile {
+                status::ModulefileState::Missing => "missing",
+                status::ModulefileState::Outdated => "outdated",
+                status::ModulefileState::UpToDate => "up to date",
+            };
+
+            rows.push(vec![
+                m.identifier(),
+                status.flavour,
+                source,
+                (if status.built { "yes" } else { "no" }).to_string(),
+                installed,
+                modulefile.to_string(),
+                if status.stale.is_empty() {
+                    "-".to_string()
+                } else {
+                    status.stale.join(", ")
+                },
+            ]);
+        }
+    }
+
+    cli::print_table(
+        &[
+            "Module",
+            "Flavour",
+            "Source",
+            "Built",
+            "Installed",
+            "Modulefile",
+            "Stale",
+        ],
+        &rows,
+    );
+
+    Ok(())
+}
+
+/// A callback function to print the status of a module.
+///
+/// # Errors
+///
+/// Errors if a single module cannot be resolved from the specified name,
+/// or if its status cannot be determined.
+pub fn status_module(
+    partials: &[&str],
+    _flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    resolver_boilerplate(partials, |m| {
+        print_status(std::slice::from_ref(m), config)
+    })
+}
+
+/// A callback function to print the status of all available modules.
+///
+/// # Errors
+///
+/// Errors if the modules cannot be listed or if the status of any module
+/// cannot be determined.
+pub fn status_all(
+    _flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    print_status(&get_modules()?, config)
+}
+
+/// A callback function to uninstall a module.
+///
+/// # Errors
+///
+/// Errors if a single module cannot be resolved from the specified name,
+/// or if the call to [`uninstall`] fails.
+pub fn uninstall_module(
+    partials: &[&str],
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let selection = flavour_selection(flags, config)?;
+    let force = flags.is_set("force");
+    let yes = flags.is_set("yes");
+
+    resolver_boilerplate(partials, |m| {
+        uninstall(m, &selection, force, yes, config)
+    })
+}
+
+/// A callback function to clean the build directories of a module.
+///
+/// # Errors
+///
+/// Errors if a single module cannot be resolved from the specified name,
+/// or if the call to [`clean`] fails.
+pub fn clean_module(
     partials: &[&str],
+    flags: &cli::Flags,
+    _config: &config::Config,
+) -> Result<(), String> {
+    let sources = flags.is_set("sources");
+    let dry_run = flags.is_set("dry-run");
+
+    resolver_boilerplate(partials, |m| clean(m, sources, dry_run))
+}
+
+/// A callback function to clean the build directories of all modules.
+///
+/// # Errors
+///
+/// Errors if the modules cannot be listed or if any module fails to clean.
+pub fn clean_all(
+    flags: &cli::Flags,
+    _config: &config::Config,
+) -> Result<(), String> {
+    let sources = flags.is_set("sources");
+    let dry_run = flags.is_set("dry-run");
+
+    for m in &get_modules()? {
+        clean(m, sources, dry_run)?;
+    }
+
+    Ok(())
+}
+
+/// A callback function to print the disk usage of a module.
+///
+/// # Errors
+///
+/// Errors if a single module cannot be resolved from the specified name,
+/// or if its disk usage cannot be determined.
+pub fn du_module(
+    partials: &[&str],
+    _flags: &cli::Flags,
+    _config: &config::Config,
+) -> Result<(), String> {
+    resolver_boilerplate(partials, |m| {
+        print_disk_usage(std::slice::from_ref(m))
+    })
+}
+
+/// A callback function to print the disk usage of all available modules.
+///
+/// # Errors
+///
+/// Errors if the modules cannot be listed or their disk usage cannot be
+/// determined.
+pub fn du_all(
+    _flags: &cli::Flags,
     _config: &config::Config,
 ) -> Result<(), String> {
-    resolver_boilerplate(partials, module::modulefile)
+    print_disk_usage(&get_modules()?)
+}
+
+/// A callback function to collect garbage in the build and install roots.
+///
+/// # Errors
+///
+/// Errors if `--days` is not a number or if [`collect_garbage`] fails.
+pub fn gc(flags: &cli::Flags, config: &config::Config) -> Result<(), String> {
+    let days = flags.value("days").map_or(Ok(30), |days| {
+        days.parse::<u64>()
+            .map_err(|_| format!("Invalid number of days '{days}'"))
+    })?;
+
+    collect_garbage(days, flags.is_set("dry-run"), flags.is_set("yes"), config)
+}
+
+/// A callback function to set the default version of a module, given its name
+/// and version.
+///
+/// # Errors
+///
+/// Errors if the arguments are not a name and a version, no module matches
+/// them, or the default cannot be set.
+pub fn default_set(
+    args: &[&str],
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let [name, version] = args else {
+        return Err("Usage: sccmod default set <name> <version>".to_string());
+    };
+
+    let modules = get_modules()?;
+    let selection =
+        flavours::Selection::new(config, flags.value("flavour"), &modules)?;
+
+    for module in defaults::select(&modules, name, version)? {
+        defaults::set(module, &modules, &selection, config)?;
+    }
+
+    Ok(())
+}
+
+/// A callback function to remove the default version of a module, given its
+/// name.
+///
+/// # Errors
+///
+/// Errors if no module has the given name or the default cannot be removed.
+pub fn default_unset(
+    args: &[&str],
+    _flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let modules = get_modules()?;
+    let selected: Vec<&Module> = modules
+        .iter()
+        .filter(|m| args.iter().any(|name| m.name.eq_ignore_ascii_case(name)))
+        .collect();
+
+    if selected.is_empty() {
+        return Err(format!("No module named '{}'", args.join(" ")));
+    }
+
+    defaults::unset(&selected, &modules, config)
+}
+
+/// Print how the modulefiles of `module` on disk differ from freshly
+/// generated ones, without writing anything. See [`Module::modulefile`] for
+/// `force`.
+///
+/// # Errors
+/// Errors if the modulefiles cannot be generated.
+pub fn diff_modulefile(
+    module: &Module,
+    modules: &[Module],
+    force: bool,
+    config: &config::Config,
+) -> Result<(), String> {
+    for output in modulefile::outputs(module, modules, config, force)? {
+        let current = std::fs::read_to_string(&output.path).unwrap_or_default();
+        let hunks = diff::unified(&current, &output.content, 3);
+
+        if hunks.is_empty() {
+            continue;
+        }
+
+        println!("{}", format!("--- {}", output.path).bold());
+        println!("{}", format!("+++ {} (generated)", output.path).bold());
+
+        for line in hunks {
+            match line.chars().next() {
+                Some('@') => println!("{}", line.cyan()),
+                Some('-') => println!("{}", line.red()),
+                Some('+') => println!("{}", line.green()),
+                _ => println!("{line}"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Report modulefiles of `modules` which were edited by hand or load an
+/// install path which no longer exists.
+///
+/// # Errors
+/// Errors if the install database cannot be read, the modulefiles cannot be
+/// checked, or any problems are found.
+pub fn check_modulefiles(
+    modules: &[Module],
+    config: &config::Config,
+) -> Result<(), String> {
+    let records = database::records(config)?;
+
+    let mut rows = Vec::new();
+    for m in modules {
+        rows.extend(
+            status::check(m, modules, &records, config)?
+                .into_iter()
+                .map(|problem| vec![problem.path, problem.problem]),
+        );
+    }
+
+    if rows.is_empty() {
+        log::info("All modulefiles are as generated");
+        return Ok(());
+    }
+
+    cli::print_table(&["Modulefile", "Problem"], &rows);
+
+    Err(format!("Found {} modulefile problem(s)", rows.len()))
+}
+
+/// A callback function to write the modulefiles of a module, or show how
+/// they would change with `--diff`.
+///
+/// # Errors
+///
+/// Errors if a single module cannot be resolved from the specified name,
+/// or if its modulefiles cannot be generated or written.
+pub fn write_modulefile(
+    partials: &[&str],
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let force = flags.is_set("force");
+    let modules = get_modules()?;
+
+    if flags.is_set("diff") {
+        resolver_boilerplate(partials, |m| {
+            diff_modulefile(m, &modules, force, config)
+        })
+    } else {
+        resolver_boilerplate(partials, |m| {
+            module::modulefile(m, &modules, config, force)
+        })
+    }
+}
+
+/// A callback function to check the modulefiles of all available modules.
+///
+/// # Errors
+///
+/// Errors if the modules cannot be listed, or if [`check_modulefiles`] fails.
+pub fn check_modulefiles_all(
+    _flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    check_modulefiles(&get_modules()?, config)
 }
 
 /// A callback function to install all available modules
@@ -225,18 +1328,126 @@ pub fn write_modulefile(
 /// # Errors
 ///
 /// Errors if the modules cannot be listed or if any module fails to install
-pub fn install_all(_config: &config::Config) -> Result<(), String> {
-    for m in &get_modules()? {
-        module::install(m)?;
+pub fn install_all(
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let selection = flavour_selection(flags, config)?;
+
+    let modules = get_modules()?;
+
+    if flags.is_set("with-deps") {
+        return install_with_dependencies(
+            &modules, &modules, &selection, config,
+        );
+    }
+
+    for m in &modules {
+        module::install(m, &modules, &selection, config)?;
     }
 
     Ok(())
 }
 
-pub fn write_modulefile_all(_config: &config::Config) -> Result<(), String> {
-    for m in &get_modules()? {
-        module::modulefile(m)?;
+/// A callback function to write the modulefiles of all available modules, or
+/// show how they would change with `--diff`.
+///
+/// # Errors
+///
+/// Errors if the modules cannot be listed, or if any modulefile cannot be
+/// generated or written.
+pub fn write_modulefile_all(
+    flags: &cli::Flags,
+    config: &config::Config,
+) -> Result<(), String> {
+    let force = flags.is_set("force");
+
+    let modules = get_modules()?;
+
+    for m in &modules {
+        if flags.is_set("diff") {
+            diff_modulefile(m, &modules, force, config)?;
+        } else {
+            module::modulefile(m, &modules, config, force)?;
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{module::Dependency, modulefile::Output, testing};
+
+    fn output(path: &str, flavour: Option<String>) -> Output {
+        Output { path: path.to_string(), flavour, content: String::new() }
+    }
+
+    #[test]
+    fn flavour_filtered_uninstall() {
+        let dir = testing::temp_dir("uninstall");
+        let root = dir.to_str().unwrap();
+
+        let modules = vec![
+            testing::module(root, "compiler/gcc/12", Vec::new()),
+            testing::module(root, "compiler/gcc/13", Vec::new()),
+        ];
+        let app = testing::module(
+            root,
+            "apps/app/1.0",
+            vec![Dependency::Class("compiler".to_string())],
+        );
+
+        let all = flavours::generate_with(&app, &modules).unwrap();
+        let (gcc12, gcc13) = (&all[0], &all[1]);
+        let (name12, name13) =
+            (flavours::gen_name(gcc12), flavours::gen_name(gcc13));
+
+        let records = vec![
+            testing::record(&app, gcc12, 1),
+            testing::record(&app, gcc13, 1),
+            testing::record(&app, gcc13, 2),
+            testing::record(&modules[0], &(Vec::new(), 0), 1),
+        ];
+
+        let outputs = || {
+            vec![
+                output("lmod/gcc-12/app/1.0", Some(name12.clone())),
+                output("lmod/gcc-13/app/1.0", Some(name13.clone())),
+                output("tcl/app/1.0", None),
+            ]
+        };
+
+        let removal = uninstall_removal(
+            &app,
+            std::slice::from_ref(gcc13),
+            &records,
+            &[1, 2],
+            outputs(),
+        );
+
+        let install = &app.install_path;
+        assert_eq!(
+            removal.paths,
+            vec![format!("{install}/1/gcc-13"), format!("{install}/2/gcc-13")]
+        );
+        assert!(removal.records.iter().all(|rec| rec.flavour == name13));
+        assert_eq!(removal.records.len(), 2);
+
+        // gcc 12 is still installed, so the shared modulefile is kept
+        assert_eq!(removal.modulefiles, vec!["lmod/gcc-13/app/1.0"]);
+
+        // Removing the last flavour removes the shared modulefile too
+        let removal =
+            uninstall_removal(&app, &all, &records, &[1, 2], outputs());
+        assert_eq!(removal.records.len(), 3);
+        assert_eq!(removal.paths.len(), 4);
+        assert_eq!(
+            removal.modulefiles,
+            vec!["lmod/gcc-12/app/1.0", "lmod/gcc-13/app/1.0", "tcl/app/1.0"]
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
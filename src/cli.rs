@@ -7,6 +7,7 @@ use std::{
 
 use anstyle::AnsiColor;
 use clap::builder::styling::Styles;
+use colored::Colorize;
 
 use crate::{config, log};
 
@@ -101,10 +102,44 @@ pub fn child_logger(
     (spawn.wait(), stdout, stderr)
 }
 
+/// Print `rows` as a table with aligned columns below a bold `header`
+pub fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i).map(String::len))
+                .chain(std::iter::once(header[i].len()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let format_row = |row: &[&str]| {
+        row.iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{value:width$}"))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", format_row(header).bold());
+
+    for row in rows {
+        let row: Vec<&str> = row.iter().map(String::as_str).collect();
+        println!("{}", format_row(&row));
+    }
+}
+
 pub enum NumParams {
     Single,
     Multi(usize),
     Any,
+
+    /// Every value after `--`, available to the other arguments' callbacks
+    /// through [`Flags::trailing`]
+    Last,
 }
 
 pub trait CommandBuilder {
@@ -137,23 +172,68 @@ impl CommandBuilder for clap::Command {
         help: &'static str,
         num_params: &NumParams,
     ) -> Self {
-        self.arg(
-            clap::Arg::new(name)
-                .help(help)
+        let arg = clap::Arg::new(name).help(help);
+
+        self.arg(match num_params {
+            NumParams::Last => arg
+                .num_args(1..)
+                .last(true)
+                .allow_hyphen_values(true)
+                .action(clap::ArgAction::Set),
+            _ => arg
                 .num_args(
                     1..=match num_params {
                         NumParams::Single => 1,
                         NumParams::Multi(n) => *n,
-                        NumParams::Any => usize::MAX,
+                        NumParams::Any | NumParams::Last => usize::MAX,
                     },
                 )
                 .action(clap::ArgAction::Set),
-        )
+        })
     }
 }
 
-type CommandCallback = fn(&config::Config) -> Result<(), String>;
-type ArgumentCallback = fn(&[&str], &config::Config) -> Result<(), String>;
+type CommandCallback = fn(&Flags, &config::Config) -> Result<(), String>;
+type ArgumentCallback =
+    fn(&[&str], &Flags, &config::Config) -> Result<(), String>;
+
+/// An optional `--name` flag. Flags are available to the command they are
+/// defined on and all of its subcommands.
+pub struct Flag {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub takes_value: bool,
+}
+
+/// The flags passed to a command
+#[derive(Debug, Default, Clone)]
+pub struct Flags {
+    values: Vec<(&'static str, Option<String>)>,
+    trailing: Vec<String>,
+}
+
+impl Flags {
+    /// Returns `true` if the flag `name` was passed
+    #[must_use]
+    pub fn is_set(&self, name: &str) -> bool {
+        self.values.iter().any(|(flag, _)| *flag == name)
+    }
+
+    /// Returns the value passed to the flag `name`, if any
+    #[must_use]
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(flag, _)| *flag == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// Returns the values passed after `--`, if the command takes them
+    #[must_use]
+    pub fn trailing(&self) -> &[String] {
+        &self.trailing
+    }
+}
 
 pub struct Arg {
     pub name: &'static str,
@@ -166,6 +246,7 @@ pub struct Command {
     pub name: &'static str,
     pub subcommands: Vec<Command>,
     pub arguments: Vec<Arg>,
+    pub flags: Vec<Flag>,
     pub help: &'static str,
     pub callback: Option<CommandCallback>,
 }
@@ -195,6 +276,20 @@ impl Command {
             res = res.add_argument(arg.name, arg.help, &arg.num_params);
         }
 
+        for flag in &self.flags {
+            res = res.arg(
+                clap::Arg::new(flag.name)
+                    .long(flag.name)
+                    .help(flag.help)
+                    .global(true)
+                    .action(if flag.takes_value {
+                        clap::ArgAction::Set
+                    } else {
+                        clap::ArgAction::SetTrue
+                    }),
+            );
+        }
+
         res
     }
 
@@ -208,8 +303,45 @@ impl Command {
         config: &config::Config,
         matches: &clap::ArgMatches,
     ) -> Result<(), String> {
+        self.consume_with_flags(config, matches, Flags::default())
+    }
+
+    fn consume_with_flags(
+        &self,
+        config: &config::Config,
+        matches: &clap::ArgMatches,
+        mut flags: Flags,
+    ) -> Result<(), String> {
+        for flag in &self.flags {
+            let value = if flag.takes_value {
+                matches.get_one::<String>(flag.name).cloned().map(Some)
+            } else {
+                matches.get_flag(flag.name).then_some(None)
+            };
+
+            if let Some(value) = value {
+                flags.values.push((flag.name, value));
+            }
+        }
+
+        let is_last = |arg: &&Arg| matches!(arg.num_params, NumParams::Last);
+
+        for arg in self.arguments.iter().filter(is_last) {
+            if let Some(values) = matches.get_many::<String>(arg.name) {
+                flags.trailing = values.cloned().collect();
+            }
+        }
+
+        // Values after `--` are only handled by their own callback if no
+        // other argument was given
+        let mut arguments: Vec<&Arg> =
+            self.arguments.iter().filter(|arg| !is_last(arg)).collect();
+        if !arguments.iter().any(|arg| matches.contains_id(arg.name)) {
+            arguments.extend(self.arguments.iter().filter(is_last));
+        }
+
         let mut arg_count = 0;
-        for arg in &self.arguments {
+        for arg in arguments {
             // if let Some(value) = matches.get_one::<String>(arg.name) {
             //     (arg.callback)(value, config)?;
             //     arg_count += 1;
@@ -222,7 +354,7 @@ impl Command {
             if let Some(values) = matches.get_many::<String>(arg.name) {
                 let values: Vec<&str> =
                     values.map(std::string::String::as_str).collect();
-                (arg.callback)(&values, config)?;
+                (arg.callback)(&values, &flags, config)?;
                 arg_count += 1;
 
                 if arg_count > 1 {
@@ -234,7 +366,7 @@ impl Command {
         let mut sub_count = 0;
         for sub in &self.subcommands {
             if let Some(matches) = matches.subcommand_matches(sub.name) {
-                sub.consume(config, matches)?;
+                sub.consume_with_flags(config, matches, flags.clone())?;
                 sub_count += 1;
 
                 if sub_count + arg_count > 1 {
@@ -245,7 +377,7 @@ impl Command {
 
         if arg_count + sub_count == 0 {
             if let Some(callback) = self.callback {
-                callback(config)?;
+                callback(&flags, config)?;
             }
         }
 
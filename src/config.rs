@@ -1,9 +1,24 @@
-use std::fs;
+use std::{collections::HashMap, fs};
 
 use toml::Table;
 
 use crate::module::Module;
 
+/// The kind of modulefile to generate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModulefileFormat {
+    /// Tcl modulefiles using the `flavours` extension
+    Tcl,
+
+    /// Lua modulefiles for Lmod, with flavours expressed through the module
+    /// hierarchy
+    Lua,
+
+    /// Standalone Tcl modulefiles, one per installed flavour, which work
+    /// without the `flavours` extension
+    Plain,
+}
+
 #[derive(Debug)]
 pub struct Config {
     pub sccmod_module_paths: Vec<String>,
@@ -13,6 +28,56 @@ pub struct Config {
     pub shell: String,
     pub class_no_conflict: Vec<String>,
     pub num_threads: usize,
+    pub default_flavours: HashMap<String, Vec<String>>,
+    pub modulefile_format: ModulefileFormat,
+    pub auto_environment: bool,
+    pub modulefile_template: Option<String>,
+}
+
+/// Read the optional per-class allowlist of flavours to build, e.g.
+/// `default_flavours = { compiler = ["gcc/13"] }`
+fn read_default_flavours(
+    table: &Table,
+) -> Result<HashMap<String, Vec<String>>, String> {
+    match table.get("default_flavours") {
+        Some(value) => value
+            .as_table()
+            .ok_or_else(|| {
+                "`default_flavours` must be a table of string arrays".to_string()
+            })?
+            .iter()
+            .map(|(class, partials)| {
+                partials
+                    .as_array()
+                    .and_then(|partials| {
+                        partials
+                            .iter()
+                            .map(|p| p.as_str().map(ToString::to_string))
+                            .collect::<Option<Vec<String>>>()
+                    })
+                    .map(|partials| (class.clone(), partials))
+                    .ok_or_else(|| {
+                        format!(
+                            "`default_flavours.{class}` must be an array of strings"
+                        )
+                    })
+            })
+            .collect(),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// Read the optional `modulefile_format`, defaulting to Tcl modulefiles
+fn read_modulefile_format(table: &Table) -> Result<ModulefileFormat, String> {
+    match table.get("modulefile_format").map(toml::Value::as_str) {
+        None | Some(Some("tcl")) => Ok(ModulefileFormat::Tcl),
+        Some(Some("lua")) => Ok(ModulefileFormat::Lua),
+        Some(Some("plain")) => Ok(ModulefileFormat::Plain),
+        Some(_) => {
+            Err("`modulefile_format` must be \"tcl\", \"lua\" or \"plain\""
+                .to_string())
+        }
+    }
 }
 
 /// Read the sccmod configuration toml file and return the result.
@@ -24,6 +89,10 @@ pub struct Config {
 ///  - [`module_paths`] is not an array of strings
 ///  - [`build_root`] is not a string
 ///  - [`install_root`] is not a string
+///  - [`default_flavours`] is not a table of string arrays
+///  - [`modulefile_format`] is not `"tcl"`, `"lua"` or `"plain"`
+///  - [`auto_environment`] is not a boolean
+///  - [`modulefile_template`] is not a string
 pub fn read() -> Result<Config, String> {
     // Read config file
     let config_path = std::env::var("SCCMOD_CONFIG").map_err(|_| {
@@ -98,6 +167,22 @@ pub fn read() -> Result<Config, String> {
         .try_into()
         .map_err(|_| "`num_threads` must be a positive integer".to_string())?;
 
+    let default_flavours = read_default_flavours(&table)?;
+
+    let auto_environment = table
+        .get("auto_environment")
+        .map_or(Some(false), toml::Value::as_bool)
+        .ok_or_else(|| "`auto_environment` must be a boolean".to_string())?;
+
+    let modulefile_template = table
+        .get("modulefile_template")
+        .map(|path| {
+            path.as_str().map(ToString::to_string).ok_or_else(|| {
+                "`modulefile_template` must be a string".to_string()
+            })
+        })
+        .transpose()?;
+
     // .or(Some(64i64))
     // .ok_or_else(|| "`num_threads` must be an integer".to_string())?
     // .try_into()
@@ -111,5 +196,9 @@ pub fn read() -> Result<Config, String> {
         shell,
         class_no_conflict,
         num_threads,
+        default_flavours,
+        modulefile_format: read_modulefile_format(&table)?,
+        auto_environment,
+        modulefile_template,
     })
 }
@@ -0,0 +1,503 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use toml::{Table, Value};
+
+use crate::{
+    builders::builder_trait::BuilderImpl,
+    config,
+    downloaders::DownloaderImpl,
+    environment,
+    file_manager::{recursive_list_dir, PATH_SEP},
+    flavours, hash, log,
+    module::Module,
+};
+
+/// Directory under `install_root` containing one record per installed flavour
+const DATABASE_DIR: &str = ".sccmod/installs";
+
+/// A record of a single installed flavour of a module, stored as a TOML file
+/// under `install_root`
+#[derive(Debug, Clone)]
+pub struct InstallRecord {
+    /// The module identifier (`class/name/version`)
+    pub identifier: String,
+
+    /// The flavour name, as generated by [`flavours::gen_name`]
+    pub flavour: String,
+
+    /// `name/version` of every module the flavour was built against
+    pub dependencies: Vec<String>,
+
+    pub revision: usize,
+
+    /// The build fingerprint of the module when it was installed
+    pub fingerprint: String,
+
+    /// The commit, hash or URL the source code was downloaded from
+    pub source_revision: Option<String>,
+
+    /// The builder settings used to build the module, serialised by
+    /// [`BuilderImpl::fingerprint`]
+    pub builder: String,
+
+    /// Seconds since the Unix epoch when the flavour was installed
+    pub installed_at: u64,
+
+    pub install_path: String,
+
+    /// `(variable, path)` pairs detected in the install prefix when the
+    /// module opts into automatic environment detection
+    pub environment: Vec<(String, String)>,
+
+    /// The modulefile written for the module, if any
+    pub modulefile: Option<String>,
+
+    /// Seconds since the Unix epoch when the modulefile was written
+    pub modulefile_written_at: Option<u64>,
+}
+
+/// Seconds since the Unix epoch
+#[must_use]
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The root directory of the install database
+#[must_use]
+pub fn root(config: &config::Config) -> PathBuf {
+    Path::new(&config.install_root).join(DATABASE_DIR)
+}
+
+impl InstallRecord {
+    /// Create a record for a flavour of `module` which has just been
+    /// installed.
+    #[must_use]
+    pub fn new(module: &Module, flavour: &(Vec<Module>, usize)) -> Self {
+        let (_, _, install_path, _) = module.parse(&(&flavour.0, flavour.1));
+
+        let source_revision =
+            module.downloader.as_ref().and_then(|downloader| {
+                downloader.source_revision(&module.source_path)
+            });
+
+        let builder = hash::canonical_option(
+            module.builder.as_ref().map(BuilderImpl::fingerprint).as_ref(),
+        );
+
+        let environment = if module.auto_environment {
+            environment::detect(&install_path)
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            identifier: module.identifier(),
+            flavour: flavours::gen_name(flavour),
+            dependencies: flavour.0.iter().map(Module::mod_name).collect(),
+            revision: module.revision,
+            fingerprint: module.fingerprint(),
+            source_revision,
+            builder,
+            installed_at: timestamp(),
+            install_path,
+            environment,
+            modulefile: None,
+            modulefile_written_at: None,
+        }
+    }
+
+    /// The path of the file storing this record
+    #[must_use]
+    pub fn path(&self, config: &config::Config) -> PathBuf {
+        let flavour = self
+            .install_path
+            .rsplit(PATH_SEP)
+            .next()
+            .unwrap_or("default")
+            .to_string();
+
+        root(config)
+            .join(&self.identifier)
+            .join(format!("{}-{flavour}.toml", self.revision))
+    }
+
+    /// Returns `true` if this record belongs to `module`
+    #[must_use]
+    pub fn is_for(&self, module: &Module) -> bool {
+        self.identifier == module.identifier()
+    }
+
+    /// Returns `true` if this record is of `flavour` of `module` at its
+    /// current revision
+    #[must_use]
+    pub fn matches(
+        &self,
+        module: &Module,
+        flavour: &(Vec<Module>, usize),
+    ) -> bool {
+        self.is_for(module)
+            && self.revision == module.revision
+            && self.flavour == flavours::gen_name(flavour)
+    }
+
+    fn to_table(&self) -> Table {
+        let mut table = Table::new();
+
+        let mut insert = |key: &str, value: Value| {
+            table.insert(key.to_string(), value);
+        };
+
+        insert("identifier", Value::String(self.identifier.clone()));
+        insert("flavour", Value::String(self.flavour.clone()));
+        insert(
+            "dependencies",
+            Value::Array(
+                self.dependencies.iter().cloned().map(Value::String).collect(),
+            ),
+        );
+        insert(
+            "revision",
+            Value::Integer(self.revision.try_into().unwrap_or(0)),
+        );
+        insert("fingerprint", Value::String(self.fingerprint.clone()));
+        insert("builder", Value::String(self.builder.clone()));
+        insert(
+            "installed_at",
+            Value::Integer(self.installed_at.try_into().unwrap_or(0)),
+        );
+        insert("install_path", Value::String(self.install_path.clone()));
+
+        if !self.environment.is_empty() {
+            insert(
+                "environment",
+                Value::Array(
+                    self.environment
+                        .iter()
+                        .map(|(var, path)| {
+                            Value::Array(vec![
+                                Value::String(var.clone()),
+                                Value::String(path.clone()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            );
+        }
+
+        if let Some(source_revision) = &self.source_revision {
+            insert("source_revision", Value::String(source_revision.clone()));
+        }
+
+        if let Some(modulefile) = &self.modulefile {
+            insert("modulefile", Value::String(modulefile.clone()));
+        }
+
+        if let Some(written_at) = self.modulefile_written_at {
+            insert(
+                "modulefile_written_at",
+                Value::Integer(written_at.try_into().unwrap_or(0)),
+            );
+        }
+
+        table
+    }
+
+    fn from_table(table: &Table) -> Result<Self, String> {
+        let string = |key: &str| {
+            table
+                .get(key)
+                .and_then(Value::as_str)
+                .map(ToString::to_string)
+                .ok_or_else(|| format!("Install record is missing '{key}'"))
+        };
+
+        let integer = |key: &str| {
+            table
+                .get(key)
+                .and_then(Value::as_integer)
+                .and_then(|value| u64::try_from(value).ok())
+        };
+
+        Ok(Self {
+            identifier: string("identifier")?,
+            flavour: string("flavour")?,
+            dependencies: table
+                .get("dependencies")
+                .and_then(Value::as_array)
+                .map(|deps| {
+                    deps.iter()
+                        .filter_map(Value::as_str)
+                        .map(ToString::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            revision: integer("revision")
+                .and_then(|rev| usize::try_from(rev).ok())
+                .ok_or("Install record is missing 'revision'")?,
+            fingerprint: string("fingerprint")?,
+            source_revision: string("source_revision").ok(),
+            builder: string("builder")?,
+            installed_at: integer("installed_at").unwrap_or_default(),
+            install_path: string("install_path")?,
+            environment: table
+                .get("environment")
+                .and_then(Value::as_array)
+                .map(|pairs| {
+                    pairs
+                        .iter()
+                        .filter_map(|pair| match pair.as_array()?.as_slice() {
+                            [var, path] => Some((
+                                var.as_str()?.to_string(),
+                                path.as_str()?.to_string(),
+                            )),
+                            _ => None,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            modulefile: string("modulefile").ok(),
+            modulefile_written_at: integer("modulefile_written_at"),
+        })
+    }
+
+    /// Write this record to the install database, replacing any existing
+    /// record of the same flavour and revision.
+    ///
+    /// # Errors
+    /// Errors if the record cannot be written.
+    pub fn write(&self, config: &config::Config) -> Result<(), String> {
+        let path = self.path(config);
+
+        path.parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(&path, self.to_table().to_string()))
+            .map_err(|err| {
+                format!(
+                    "Failed to write install record {}: {err}",
+                    path.display()
+                )
+            })
+    }
+
+    /// Remove this record from the install database.
+    ///
+    /// # Errors
+    /// Errors if the record exists but cannot be removed.
+    pub fn remove(&self, config: &config::Config) -> Result<(), String> {
+        let path = self.path(config);
+
+        match std::fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!(
+                    "Failed to remove install record {}: {err}",
+                    path.display()
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Read every record in the install database. Records which cannot be read or
+/// parsed are skipped with a warning.
+///
+/// # Errors
+/// Errors if the install database cannot be listed.
+pub fn records(config: &config::Config) -> Result<Vec<InstallRecord>, String> {
+    let root = root(config);
+
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(recursive_list_dir(&root)
+        .ok_or_else(|| {
+            format!("Failed to read install database {}", root.display())
+        })?
+        .iter()
+        .filter(|entry| {
+            entry.path().extension().is_some_and(|ext| ext == "toml")
+        })
+        .filter_map(|entry| {
+            let path = entry.path();
+
+            std::fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|content| {
+                    content.parse::<Table>().map_err(|err| err.to_string())
+                })
+                .and_then(|table| InstallRecord::from_table(&table))
+                .map_err(|err| {
+                    log::warn(&format!(
+                        "Skipping invalid install record {}: {err}",
+                        path.display()
+                    ));
+                })
+                .ok()
+        })
+        .collect())
+}
+
+/// Read the install records of `module`, across all revisions.
+///
+/// # Errors
+/// Errors if the install database cannot be read.
+pub fn records_for(
+    module: &Module,
+    config: &config::Config,
+) -> Result<Vec<InstallRecord>, String> {
+    Ok(records(config)?.into_iter().filter(|rec| rec.is_for(module)).collect())
+}
+
+/// Find the record of a specific flavour of `module` at its current revision.
+///
+/// # Errors
+/// Errors if the install database cannot be read.
+pub fn find(
+    module: &Module,
+    flavour: &(Vec<Module>, usize),
+    config: &config::Config,
+) -> Result<Option<InstallRecord>, String> {
+    Ok(records_for(module, config)?
+        .into_iter()
+        .find(|rec| rec.matches(module, flavour)))
+}
+
+/// The records of the newest installed revision of each flavour of `module`.
+///
+/// Flavours are installed independently, so each may be at a different
+/// revision. Records whose install directory no longer exists are ignored.
+#[must_use]
+pub fn latest<'a>(
+    records: &'a [InstallRecord],
+    module: &Module,
+) -> Vec<&'a InstallRecord> {
+    let installed: Vec<&InstallRecord> = records
+        .iter()
+        .filter(|rec| {
+            rec.is_for(module) && Path::new(&rec.install_path).exists()
+        })
+        .collect();
+
+    installed
+        .iter()
+        .filter(|rec| {
+            installed.iter().all(|other| {
+                other.flavour != rec.flavour || other.revision <= rec.revision
+            })
+        })
+        .copied()
+        .collect()
+}
+
+/// The newest installed revision of the flavour named `flavour` of `module`,
+/// or [`None`] if it is not installed
+#[must_use]
+pub fn latest_revision(
+    records: &[InstallRecord],
+    module: &Module,
+    flavour: &str,
+) -> Option<usize> {
+    latest(records, module)
+        .iter()
+        .find(|rec| rec.flavour == flavour)
+        .map(|rec| rec.revision)
+}
+
+/// `module` at the newest installed revision of `flavour`, or at
+/// [`Module::latest_revision`] if the flavour is not recorded as installed
+#[must_use]
+pub fn at_latest_revision(
+    records: &[InstallRecord],
+    module: &Module,
+    flavour: &(Vec<Module>, usize),
+) -> Module {
+    let revision =
+        latest_revision(records, module, &flavours::gen_name(flavour))
+            .unwrap_or_else(|| module.latest_revision());
+
+    Module { revision, ..module.clone() }
+}
+
+/// Returns `true` if `records` show `flavour` of `module` as installed at its
+/// current revision and the install directory still exists.
+#[must_use]
+pub fn is_installed(
+    records: &[InstallRecord],
+    module: &Module,
+    flavour: &(Vec<Module>, usize),
+) -> bool {
+    records.iter().any(|rec| {
+        rec.matches(module, flavour) && Path::new(&rec.install_path).exists()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn record(root: &str) -> InstallRecord {
+        let module = testing::module(root, "tools/hello/1.0", Vec::new());
+
+        InstallRecord {
+            source_revision: Some("0123abcd".to_string()),
+            environment: vec![("PATH".to_string(), "bin".to_string())],
+            modulefile: Some(format!("{root}/modulefiles/tools/hello/1.0")),
+            modulefile_written_at: Some(1_700_000_000),
+            installed_at: 1_600_000_000,
+            ..testing::record(&module, &(Vec::new(), 0), 2)
+        }
+    }
+
+    #[test]
+    fn table_round_trip() {
+        let dir = testing::temp_dir("record-table");
+        let record = record(dir.to_str().unwrap());
+
+        let table: Table = record.to_table().to_string().parse().unwrap();
+        let parsed = InstallRecord::from_table(&table).unwrap();
+        assert_eq!(format!("{parsed:?}"), format!("{record:?}"));
+
+        // Optional fields may be left out
+        let record = InstallRecord {
+            source_revision: None,
+            environment: Vec::new(),
+            modulefile: None,
+            modulefile_written_at: None,
+            ..record
+        };
+        let parsed = InstallRecord::from_table(&record.to_table()).unwrap();
+        assert_eq!(format!("{parsed:?}"), format!("{record:?}"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn corrupt_records_are_skipped() {
+        let dir = testing::temp_dir("corrupt-record");
+        let root = dir.to_str().unwrap();
+        let config = testing::config(root);
+
+        let record = record(root);
+        record.write(&config).unwrap();
+
+        let corrupt = super::root(&config).join("tools/broken/1.0");
+        std::fs::create_dir_all(&corrupt).unwrap();
+        std::fs::write(corrupt.join("1-default.toml"), "identifier = [")
+            .unwrap();
+        std::fs::write(corrupt.join("2-default.toml"), "flavour = \"|\"")
+            .unwrap();
+
+        let records = records(&config).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].identifier, "tools/hello/1.0");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
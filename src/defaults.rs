@@ -0,0 +1,316 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::{self, ModulefileFormat},
+    flavours::{self, Selection},
+    log,
+    module::Module,
+    modulefile,
+    version::Version,
+};
+
+/// Name of the file selecting the default version in a Tcl modulefile
+/// directory
+const VERSION_FILE: &str = ".version";
+
+/// Name of the symlink selecting the default version in an Lmod modulefile
+/// directory
+const DEFAULT_LINK: &str = "default";
+
+/// A modulefile of a module which may be selected as the default
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Target {
+    dir: PathBuf,
+    file: String,
+
+    /// The flavour the modulefile loads, or [`None`] if it covers every
+    /// flavour
+    flavour: Option<String>,
+}
+
+/// The modulefiles of `module` which may be selected as the default, sorted
+/// by directory. Modules may have several directories, such as one per
+/// hierarchy level for Lmod. Every flavour is included, as its modulefile may
+/// have been written with `--force`.
+fn targets(
+    module: &Module,
+    modules: &[Module],
+    config: &config::Config,
+) -> Result<Vec<Target>, String> {
+    let mut targets: Vec<Target> =
+        modulefile::outputs(module, modules, config, true)?
+            .into_iter()
+            .filter_map(|output| {
+                let path = Path::new(&output.path);
+
+                Some(Target {
+                    dir: path.parent()?.to_path_buf(),
+                    file: path.file_name()?.to_str()?.to_string(),
+                    flavour: output.flavour,
+                })
+            })
+            .collect();
+
+    targets.sort();
+    Ok(targets)
+}
+
+/// Choose the default modulefile among `candidates`, which share a
+/// directory. A modulefile covering every flavour is preferred, otherwise the
+/// single one whose flavour of `module` is chosen by `selection`.
+///
+/// # Errors
+/// Errors if `selection` chooses none or several of the candidates.
+fn choose<'a>(
+    module: &Module,
+    modules: &[Module],
+    candidates: &[&'a Target],
+    selection: &Selection,
+) -> Result<&'a Target, String> {
+    if let Some(target) = candidates.iter().find(|t| t.flavour.is_none()) {
+        return Ok(target);
+    }
+
+    if let [target] = candidates {
+        return Ok(target);
+    }
+
+    let selected: Vec<String> = selection
+        .filter(flavours::generate_with(module, modules)?)
+        .iter()
+        .map(flavours::gen_name)
+        .collect();
+
+    let chosen: Vec<&'a Target> = candidates
+        .iter()
+        .copied()
+        .filter(|t| t.flavour.as_ref().is_some_and(|f| selected.contains(f)))
+        .collect();
+
+    if let [target] = chosen.as_slice() {
+        return Ok(target);
+    }
+
+    let ambiguous = if chosen.is_empty() { candidates } else { &chosen };
+
+    Err(format!(
+        "Cannot choose the default modulefile of '{}' among {}. Select a \
+single flavour with --flavour or `default_flavours`",
+        module.identifier(),
+        ambiguous
+            .iter()
+            .map(|t| t.file.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    ))
+}
+
+/// Create a symlink at `link` pointing to `target`
+#[cfg(unix)]
+fn symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Lmod default symlinks are only supported on Unix
+#[cfg(not(unix))]
+fn symlink(_target: &str, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "default symlinks are only supported on Unix",
+    ))
+}
+
+/// Remove the default marker in `dir`, if there is one
+fn remove_marker(dir: &Path, config: &config::Config) -> Result<(), String> {
+    let marker = match config.modulefile_format {
+        ModulefileFormat::Tcl | ModulefileFormat::Plain => {
+            dir.join(VERSION_FILE)
+        }
+        ModulefileFormat::Lua => dir.join(DEFAULT_LINK),
+    };
+
+    match std::fs::remove_file(&marker) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(format!(
+            "Failed to remove default marker {}: {err}",
+            marker.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Make `module` the version loaded when only its name is given, by writing a
+/// `.version` file (Tcl) or a `default` symlink (Lmod) next to each of its
+/// modulefiles.
+///
+/// Where flavours share a directory, as with plain modulefiles, the flavour
+/// chosen by `selection` is made the default. Dependencies are resolved among
+/// the available `modules`.
+///
+/// # Errors
+/// Errors if none of the modulefiles of `module` have been written, if
+/// `selection` does not choose a single flavour where several share a
+/// directory, or a marker cannot be written.
+pub fn set(
+    module: &Module,
+    modules: &[Module],
+    selection: &Selection,
+    config: &config::Config,
+) -> Result<(), String> {
+    let written: Vec<Target> = targets(module, modules, config)?
+        .into_iter()
+        .filter(|t| t.dir.join(&t.file).exists())
+        .collect();
+
+    if written.is_empty() {
+        return Err(format!(
+            "No modulefiles have been written for '{}'. Run `sccmod modulefile` first",
+            module.identifier()
+        ));
+    }
+
+    let mut dirs: Vec<&PathBuf> = written.iter().map(|t| &t.dir).collect();
+    dirs.dedup();
+
+    for dir in dirs {
+        let candidates: Vec<&Target> =
+            written.iter().filter(|t| &t.dir == dir).collect();
+        let file = &choose(module, modules, &candidates, selection)?.file;
+
+        remove_marker(dir, config)?;
+
+        let result = match config.modulefile_format {
+            ModulefileFormat::Tcl | ModulefileFormat::Plain => std::fs::write(
+                dir.join(VERSION_FILE),
+                format!("#%Module\nset ModulesVersion \"{file}\"\n"),
+            ),
+            ModulefileFormat::Lua => symlink(file, &dir.join(DEFAULT_LINK)),
+        };
+
+        result.map_err(|err| {
+            format!(
+                "Failed to set the default version in {}: {err}",
+                dir.display()
+            )
+        })?;
+    }
+
+    log::info(&format!("Default version of {} set", module.mod_name()));
+    Ok(())
+}
+
+/// Remove the default version markers of every module in `selected`, one of
+/// the available `modules`.
+///
+/// # Errors
+/// Errors if the modulefiles of a module cannot be generated or a marker
+/// cannot be removed.
+pub fn unset(
+    selected: &[&Module],
+    modules: &[Module],
+    config: &config::Config,
+) -> Result<(), String> {
+    for module in selected {
+        let mut dirs: Vec<PathBuf> = targets(module, modules, config)?
+            .into_iter()
+            .map(|t| t.dir)
+            .collect();
+        dirs.dedup();
+
+        for dir in dirs {
+            remove_marker(&dir, config)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the modules named `name` (ignoring case) with version `version`. The
+/// version `latest` selects the highest version available.
+///
+/// # Errors
+/// Errors if no module matches.
+pub fn select<'a>(
+    modules: &'a [Module],
+    name: &str,
+    version: &str,
+) -> Result<Vec<&'a Module>, String> {
+    let named: Vec<&Module> =
+        modules.iter().filter(|m| m.name.eq_ignore_ascii_case(name)).collect();
+
+    let version = if version == "latest" {
+        named
+            .iter()
+            .filter_map(|m| Some((m.version.parse::<Version>().ok()?, m)))
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map_or(version, |(_, m)| m.version.as_str())
+    } else {
+        version
+    };
+
+    let selected: Vec<&Module> =
+        named.into_iter().filter(|m| m.version == version).collect();
+
+    if selected.is_empty() {
+        return Err(format!("No module '{name}/{version}' found"));
+    }
+
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{module::Dependency, testing};
+
+    fn target(file: &str, flavour: Option<&str>) -> Target {
+        Target {
+            dir: PathBuf::from("/modulefiles/apps/app"),
+            file: file.to_string(),
+            flavour: flavour.map(String::from),
+        }
+    }
+
+    #[test]
+    fn choose_follows_selection() {
+        let modules = vec![
+            testing::module("/opt", "compiler/gcc/12", Vec::new()),
+            testing::module("/opt", "compiler/gcc/13", Vec::new()),
+        ];
+        let app = testing::module(
+            "/opt",
+            "apps/app/1.0",
+            vec![Dependency::Class("compiler".to_string())],
+        );
+
+        let gcc12 = target("1.0-gcc-12", Some("|gcc/12|"));
+        let gcc13 = target("1.0-gcc-13", Some("|gcc/13|"));
+        let candidates = [&gcc12, &gcc13];
+
+        let selection = |partials| {
+            Selection::new(&testing::config("/opt"), partials, &modules)
+                .unwrap()
+        };
+
+        assert_eq!(
+            choose(&app, &modules, &candidates, &selection(Some("gcc/13"))),
+            Ok(&gcc13)
+        );
+        assert_eq!(
+            choose(&app, &modules, &candidates, &selection(None)).unwrap_err(),
+            "Cannot choose the default modulefile of 'apps/app/1.0' among \
+1.0-gcc-12, 1.0-gcc-13. Select a single flavour with --flavour or \
+`default_flavours`"
+        );
+
+        // A single modulefile, or one covering every flavour, needs no choice
+        assert_eq!(
+            choose(&app, &modules, &[&gcc12], &selection(None)),
+            Ok(&gcc12)
+        );
+        let all = target("1.0", None);
+        assert_eq!(
+            choose(&app, &modules, &[&gcc12, &all], &selection(None)),
+            Ok(&all)
+        );
+    }
+}
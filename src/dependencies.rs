@@ -0,0 +1,310 @@
+use std::path::Path;
+
+use crate::{
+    config, database, flavours,
+    module::{Dependency, Module},
+    modulefile,
+};
+
+/// List the modules `module` is built against.
+///
+/// This includes every module named by a `Module` or `Depends` dependency and
+/// every member of a required class which is used by at least one selected
+/// flavour. Only the classes `module` depends on are restricted by
+/// `selection`.
+///
+/// # Errors
+/// Errors if the flavours of `module` cannot be generated.
+pub fn prerequisites(
+    module: &Module,
+    modules: &[Module],
+    selection: &flavours::Selection,
+) -> Result<Vec<Module>, String> {
+    let mut result: Vec<Module> = Vec::new();
+
+    let flavs = flavours::generate_with(module, modules).map_err(|err| {
+        format!(
+            "Failed to resolve dependencies of '{}': {err}",
+            module.identifier()
+        )
+    })?;
+
+    let selection =
+        selection.restricted_to(&flavours::classes(module, modules));
+
+    for (flavour, _) in selection.filter(flavs) {
+        for dep in flavour {
+            if !result.iter().any(|m| m.identifier() == dep.identifier()) {
+                result.push(dep);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Resolve the complete dependency graph of `targets`.
+///
+/// The result contains every target and all of their transitive
+/// prerequisites, ordered such that each module appears after everything it
+/// depends on.
+///
+/// # Errors
+/// Errors if a dependency cannot be resolved or if the dependency graph
+/// contains a cycle.
+pub fn resolve(
+    targets: &[Module],
+    modules: &[Module],
+    selection: &flavours::Selection,
+) -> Result<Vec<Module>, String> {
+    let mut order = Vec::new();
+    let mut stack = Vec::new();
+
+    for target in targets {
+        visit(target, modules, selection, &mut stack, &mut order)?;
+    }
+
+    Ok(order)
+}
+
+/// Depth-first topological sort. `stack` holds the identifiers of the modules
+/// currently being visited, so finding a module already on it means there is a
+/// cycle.
+fn visit(
+    module: &Module,
+    modules: &[Module],
+    selection: &flavours::Selection,
+    stack: &mut Vec<String>,
+    order: &mut Vec<Module>,
+) -> Result<(), String> {
+    let identifier = module.identifier();
+
+    if order.iter().any(|m| m.identifier() == identifier) {
+        return Ok(());
+    }
+
+    if let Some(pos) = stack.iter().position(|id| id == &identifier) {
+        let mut cycle = stack[pos..].to_vec();
+        cycle.push(identifier);
+        return Err(format!(
+            "Dependency cycle detected: {}",
+            cycle.join(" -> ")
+        ));
+    }
+
+    stack.push(identifier);
+
+    for dep in prerequisites(module, modules, selection)? {
+        visit(&dep, modules, selection, stack, order)?;
+    }
+
+    stack.pop();
+    order.push(module.clone());
+
+    Ok(())
+}
+
+/// Returns `true` if `module` has a modulefile and every one of its selected
+/// flavours is recorded in the install database. Modules without a builder only
+/// need a modulefile.
+///
+/// A module with no modulefiles to check, or no selected flavours, is not
+/// installed.
+///
+/// # Errors
+/// Errors if the flavours or modulefiles of `module` cannot be generated.
+pub fn is_installed(
+    module: &Module,
+    modules: &[Module],
+    selection: &flavours::Selection,
+    config: &config::Config,
+) -> Result<bool, String> {
+    // Only the modulefiles of installed flavours are required
+    let modulefiles = modulefile::outputs(module, modules, config, false)?;
+
+    if !modulefiles.iter().all(|output| Path::new(&output.path).exists()) {
+        return Ok(false);
+    }
+
+    if module.builder.is_none() {
+        return Ok(!modulefiles.is_empty());
+    }
+
+    let flavs = selection.filter(flavours::generate_with(module, modules)?);
+    let records = database::records_for(module, config)?;
+
+    Ok(!flavs.is_empty()
+        && flavs
+            .iter()
+            .all(|flav| database::is_installed(&records, module, flav)))
+}
+
+/// List the installed modules which depend on `module`: those built against
+/// it according to the install database, and those which load it through a
+/// `Depends` dependency.
+#[must_use]
+pub fn installed_dependents(
+    module: &Module,
+    modules: &[Module],
+    records: &[database::InstallRecord],
+) -> Vec<String> {
+    let mod_name = module.mod_name();
+
+    let mut dependents: Vec<String> = records
+        .iter()
+        .filter(|rec| {
+            !rec.is_for(module) && rec.dependencies.contains(&mod_name)
+        })
+        .map(|rec| rec.identifier.clone())
+        .collect();
+
+    for other in modules {
+        let loads_module = other.dependencies.iter().any(|dep| {
+            matches!(dep, Dependency::Depends(spec)
+                if flavours::matches_spec(module, spec))
+        });
+
+        if loads_module && records.iter().any(|rec| rec.is_for(other)) {
+            dependents.push(other.identifier());
+        }
+    }
+
+    dependents.sort();
+    dependents.dedup();
+    dependents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{flavours::Selection, testing};
+
+    fn identifiers(modules: &[Module]) -> Vec<String> {
+        modules.iter().map(Module::identifier).collect()
+    }
+
+    fn available(root: &str) -> Vec<Module> {
+        let module = |identifier, dependencies| {
+            testing::module(root, identifier, dependencies)
+        };
+
+        vec![
+            module("compiler/gcc/12", Vec::new()),
+            module("compiler/gcc/13", Vec::new()),
+            module("libraries/zlib/1.3", Vec::new()),
+            module(
+                "libraries/hdf5/1.14",
+                vec![
+                    Dependency::Class("compiler".into()),
+                    Dependency::Module("zlib/1.3".into()),
+                ],
+            ),
+            module(
+                "apps/app/1.0",
+                vec![
+                    Dependency::Class("compiler".into()),
+                    Dependency::Depends("hdf5@>=1.12".into()),
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn resolve_orders_prerequisites_first() {
+        let modules = available("/sccmod-test");
+        let app = &modules[4];
+
+        let order =
+            resolve(std::slice::from_ref(app), &modules, &Selection::default())
+                .unwrap();
+
+        assert_eq!(
+            identifiers(&order),
+            vec![
+                "compiler/gcc/12",
+                "libraries/zlib/1.3",
+                "compiler/gcc/13",
+                "libraries/hdf5/1.14",
+                "apps/app/1.0",
+            ]
+        );
+
+        // Each module appears once, however many targets need it
+        let order = resolve(&modules, &modules, &Selection::default()).unwrap();
+        assert_eq!(order.len(), modules.len());
+    }
+
+    #[test]
+    fn resolve_applies_selection() {
+        let modules = available("/sccmod-test");
+        // zlib has no mpi flavours, so is not restricted by them
+        let selection = Selection {
+            allowed: [
+                ("compiler".to_string(), vec!["gcc/13".to_string()]),
+                ("mpi".to_string(), vec!["openmpi".to_string()]),
+            ]
+            .into(),
+        };
+
+        let order =
+            resolve(std::slice::from_ref(&modules[4]), &modules, &selection)
+                .unwrap();
+
+        assert_eq!(
+            identifiers(&order),
+            vec![
+                "compiler/gcc/13",
+                "libraries/zlib/1.3",
+                "libraries/hdf5/1.14",
+                "apps/app/1.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_detects_cycles() {
+        let modules = vec![
+            testing::module(
+                "/sccmod-test",
+                "tools/a/1",
+                vec![Dependency::Module("b/1".into())],
+            ),
+            testing::module(
+                "/sccmod-test",
+                "tools/b/1",
+                vec![Dependency::Depends("a/1".into())],
+            ),
+        ];
+
+        assert_eq!(
+            resolve(&modules[..1], &modules, &Selection::default())
+                .unwrap_err(),
+            "Dependency cycle detected: tools/a/1 -> tools/b/1 -> tools/a/1"
+        );
+    }
+
+    #[test]
+    fn nothing_selected_is_not_installed() {
+        let dir = testing::temp_dir("is-installed");
+        let root = dir.to_str().unwrap();
+        let config = testing::config(root);
+        let modules = available(root);
+
+        // No flavour of hdf5 matches, so none are recorded as installed
+        let selection = Selection {
+            allowed: [("compiler".to_string(), vec!["clang".to_string()])]
+                .into(),
+        };
+
+        assert!(
+            !is_installed(&modules[3], &modules, &selection, &config).unwrap()
+        );
+
+        // A module without a builder needs a modulefile
+        let mut zlib = modules[2].clone();
+        zlib.builder = None;
+        assert!(!is_installed(&zlib, &modules, &selection, &config).unwrap());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
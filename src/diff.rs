@@ -0,0 +1,192 @@
+/// A line of a diff between two texts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Compare `old` and `new` line by line, returning the shortest sequence of
+/// kept, removed and added lines turning one into the other.
+#[must_use]
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // common[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..]
+    let mut common = vec![vec![0_usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            result.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            result.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            result.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+
+    result.extend(old[i..].iter().map(|line| Line::Removed(line)));
+    result.extend(new[j..].iter().map(|line| Line::Added(line)));
+    result
+}
+
+/// Format the differences between `old` and `new` as the hunks of a unified
+/// diff, with `context` unchanged lines around each change. The result is
+/// empty if the texts have the same lines.
+///
+/// ```
+/// use sccmod::diff::unified;
+///
+/// let old = "a\nb\nc\nd\n";
+/// let new = "a\nB\nc\nd\n";
+///
+/// assert_eq!(unified(old, new, 1), ["@@ -1,3 +1,3 @@", " a", "-b", "+B", " c"]);
+/// assert!(unified(old, old, 3).is_empty());
+/// ```
+#[must_use]
+pub fn unified(old: &str, new: &str, context: usize) -> Vec<String> {
+    let lines = lines(old, new);
+
+    // Group the changes, and the context around them, into hunks
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (index, _) in lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+    {
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    // The number of lines of each text before each position in `lines`
+    let mut positions = vec![(0, 0)];
+    for line in &lines {
+        let (old_pos, new_pos) = positions[positions.len() - 1];
+        positions.push(match line {
+            Line::Same(_) => (old_pos + 1, new_pos + 1),
+            Line::Removed(_) => (old_pos + 1, new_pos),
+            Line::Added(_) => (old_pos, new_pos + 1),
+        });
+    }
+
+    // An empty range starts at the line before it, so an empty file is `0,0`
+    let range = |start: usize, end: usize| {
+        let count = end - start;
+        format!("{},{count}", if count == 0 { start } else { start + 1 })
+    };
+
+    let mut result = Vec::new();
+    for (start, end) in hunks {
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+
+        result.push(format!(
+            "@@ -{} +{} @@",
+            range(old_start, old_end),
+            range(new_start, new_end)
+        ));
+
+        result.extend(lines[start..end].iter().map(|line| match line {
+            Line::Same(text) => format!(" {text}"),
+            Line::Removed(text) => format!("-{text}"),
+            Line::Added(text) => format!("+{text}"),
+        }));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_file() {
+        assert_eq!(unified("", "a\nb\n", 3), ["@@ -0,0 +1,2 @@", "+a", "+b"]);
+    }
+
+    #[test]
+    fn deleted_file() {
+        assert_eq!(unified("a\nb\n", "", 3), ["@@ -1,2 +0,0 @@", "-a", "-b"]);
+    }
+
+    #[test]
+    fn both_empty() {
+        assert!(lines("", "").is_empty());
+        assert!(unified("", "", 3).is_empty());
+    }
+
+    #[test]
+    fn insertion_without_context() {
+        assert_eq!(
+            unified("a\nb\n", "a\nx\nb\n", 0),
+            ["@@ -1,0 +2,1 @@", "+x"]
+        );
+    }
+
+    #[test]
+    fn removal_without_context() {
+        assert_eq!(
+            unified("a\nx\nb\n", "a\nb\n", 0),
+            ["@@ -2,1 +1,0 @@", "-x"]
+        );
+    }
+
+    #[test]
+    fn distant_changes_make_separate_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n";
+        let new = "one\n2\n3\n4\n5\n6\n7\neight\n";
+
+        assert_eq!(
+            unified(old, new, 1),
+            [
+                "@@ -1,2 +1,2 @@",
+                "-1",
+                "+one",
+                " 2",
+                "@@ -7,2 +7,2 @@",
+                " 7",
+                "-8",
+                "+eight"
+            ]
+        );
+    }
+
+    #[test]
+    fn nearby_changes_share_a_hunk() {
+        let hunks = unified("a\nb\nc\n", "A\nb\nC\n", 1);
+
+        assert_eq!(
+            hunks.iter().filter(|line| line.starts_with("@@")).count(),
+            1
+        );
+        assert_eq!(hunks[0], "@@ -1,3 +1,3 @@");
+    }
+
+    #[test]
+    fn trailing_newline_is_ignored() {
+        assert!(unified("a\nb", "a\nb\n", 3).is_empty());
+    }
+}
@@ -0,0 +1,429 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    config,
+    database::InstallRecord,
+    file_manager::{dir_size, PATH_SEP},
+    flavours,
+    module::Module,
+};
+
+/// Disk space used by a module, or by a single flavour of it
+#[derive(Debug, Clone)]
+pub struct Usage {
+    pub identifier: String,
+
+    /// [`None`] for the module as a whole
+    pub flavour: Option<String>,
+
+    pub source: u64,
+    pub build: u64,
+    pub install: u64,
+}
+
+impl Usage {
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.source + self.build + self.install
+    }
+}
+
+/// A path which can be removed by garbage collection, and why
+#[derive(Debug, Clone)]
+pub struct Garbage {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// List the subdirectories of `path` with their names
+fn subdirs<P: AsRef<Path>>(path: &P) -> Vec<(String, PathBuf)> {
+    let mut dirs: Vec<(String, PathBuf)> = std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| {
+                    Some((
+                        entry.file_name().to_str()?.to_string(),
+                        entry.path(),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    dirs.sort();
+    dirs
+}
+
+/// List the revision directories below `path`
+fn revision_dirs<P: AsRef<Path>>(path: &P) -> Vec<(usize, PathBuf)> {
+    subdirs(path)
+        .into_iter()
+        .filter_map(|(name, path)| Some((name.parse().ok()?, path)))
+        .collect()
+}
+
+/// The directory name of a flavour, e.g. `gcc-13.1-openmpi-5.0.3`
+fn flavour_dir(module: &Module, flavour: &(Vec<Module>, usize)) -> String {
+    let (flavour_str, _, _, _) = module.parse(&(&flavour.0, flavour.1));
+
+    flavour_str.rsplit(PATH_SEP).next().unwrap_or_default().to_string()
+}
+
+/// The most recent modification time of anything below `path`
+fn last_modified<P: AsRef<Path>>(path: &P) -> Option<SystemTime> {
+    let metadata = std::fs::symlink_metadata(path).ok()?;
+    let modified = metadata.modified().ok()?;
+
+    if !metadata.is_dir() {
+        return Some(modified);
+    }
+
+    std::fs::read_dir(path)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| last_modified(&entry.path()))
+        .chain(std::iter::once(modified))
+        .max()
+}
+
+/// The configured roots and module definition paths, canonicalised, which
+/// garbage collection must never remove
+fn protected_paths(config: &config::Config) -> Vec<PathBuf> {
+    [&config.modulefile_root, &config.build_root, &config.install_root]
+        .into_iter()
+        .chain(&config.sccmod_module_paths)
+        .filter_map(|path| std::fs::canonicalize(path).ok())
+        .collect()
+}
+
+/// Whether `path` is, or contains, one of the `protected` paths
+fn contains_protected(path: &Path, protected: &[PathBuf]) -> bool {
+    std::fs::canonicalize(path).map_or(true, |path| {
+        protected.iter().any(|root| root.starts_with(&path))
+    })
+}
+
+/// Measure the disk space used by `module`: one entry for the module as a
+/// whole, followed by one for each of its flavours across all revisions.
+///
+/// # Errors
+/// Errors if the flavours of `module` cannot be generated.
+pub fn usage(
+    module: &Module,
+    modules: &[Module],
+) -> Result<Vec<Usage>, String> {
+    // The build directory is inside the source directory
+    let build = dir_size(&module.build_path);
+
+    let mut result = vec![Usage {
+        identifier: module.identifier(),
+        flavour: None,
+        source: dir_size(&module.source_path).saturating_sub(build),
+        build,
+        install: dir_size(&module.install_path),
+    }];
+
+    let build_revisions = revision_dirs(&module.build_path);
+    let install_revisions = revision_dirs(&module.install_path);
+
+    for flav in &flavours::generate_with(module, modules)? {
+        let name = flavour_dir(module, flav);
+
+        let size = |revisions: &[(usize, PathBuf)]| -> u64 {
+            revisions.iter().map(|(_, path)| dir_size(&path.join(&name))).sum()
+        };
+
+        result.push(Usage {
+            identifier: module.identifier(),
+            flavour: Some(flavours::gen_name(flav)),
+            source: 0,
+            build: size(&build_revisions),
+            install: size(&install_revisions),
+        });
+    }
+
+    Ok(result)
+}
+
+/// Find the revision and flavour directories below `path` which are no longer
+/// needed: flavours the module no longer has, and revisions in which every
+/// flavour is `superseded`, given the revision and flavour directory name.
+fn revision_garbage(
+    path: &str,
+    flavour_dirs: &[String],
+    superseded: impl Fn(usize, &str) -> bool,
+) -> Vec<Garbage> {
+    let mut garbage = Vec::new();
+
+    for (revision, rev_path) in revision_dirs(&path) {
+        let (current, removed): (Vec<_>, Vec<_>) = subdirs(&rev_path)
+            .into_iter()
+            .partition(|(name, _)| flavour_dirs.contains(name));
+
+        let reason = if current.is_empty() {
+            Some("flavour no longer generated".to_string())
+        } else if current.iter().all(|(name, _)| superseded(revision, name)) {
+            Some(format!("revision {revision} is superseded for every flavour"))
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            garbage.push(Garbage { path: rev_path, reason });
+            continue;
+        }
+
+        garbage.extend(removed.into_iter().map(|(_, path)| Garbage {
+            path,
+            reason: "flavour no longer generated".to_string(),
+        }));
+    }
+
+    garbage
+}
+
+/// Find orphaned trees, old revisions and build directories which have not
+/// been modified for `max_age`.
+///
+/// An installed revision is only old once every flavour in it has a newer
+/// revision installed according to `records`, so the only copy of a flavour
+/// is never collected.
+///
+/// Orphaned trees are directories in `build_root` or `install_root` which no
+/// current module definition or flavour maps to. Only install trees within
+/// the class directories of known modules are considered, and the configured
+/// roots are never collected, even when nested inside one another.
+///
+/// # Errors
+/// Errors if the flavours of a module cannot be generated.
+pub fn garbage(
+    modules: &[Module],
+    records: &[InstallRecord],
+    config: &config::Config,
+    max_age: Duration,
+) -> Result<Vec<Garbage>, String> {
+    let mut garbage = Vec::new();
+    let protected = protected_paths(config);
+
+    // Install trees: `install_root/class/name-version`. Other directories,
+    // such as sccmod's own data, are not ours to remove.
+    for (class, class_path) in subdirs(&config.install_root) {
+        if !modules.iter().any(|m| m.class == class) {
+            continue;
+        }
+
+        for (_, path) in subdirs(&class_path) {
+            if !modules.iter().any(|m| Path::new(&m.install_path) == path)
+                && !contains_protected(&path, &protected)
+            {
+                garbage.push(Garbage {
+                    path,
+                    reason: "no module definition".to_string(),
+                });
+            }
+        }
+    }
+
+    // Source trees: `build_root/name/version`
+    for (_, name_path) in subdirs(&config.build_root) {
+        if contains_protected(&name_path, &protected) {
+            continue;
+        }
+
+        for (_, path) in subdirs(&name_path) {
+            if !modules.iter().any(|m| Path::new(&m.source_path) == path) {
+                garbage.push(Garbage {
+                    path,
+                    reason: "no module definition".to_string(),
+                });
+            }
+        }
+    }
+
+    for module in modules {
+        let flavour_dirs: Vec<String> =
+            flavours::generate_with(module, modules)?
+                .iter()
+                .map(|flav| flavour_dir(module, flav))
+                .collect();
+
+        // An installed flavour is only superseded once a newer revision of
+        // it is recorded as installed
+        garbage.extend(revision_garbage(
+            &module.install_path,
+            &flavour_dirs,
+            |revision, name| {
+                records.iter().any(|rec| {
+                    rec.is_for(module)
+                        && rec.revision > revision
+                        && Path::new(&rec.install_path)
+                            .file_name()
+                            .is_some_and(|dir| dir == name)
+                        && Path::new(&rec.install_path).exists()
+                })
+            },
+        ));
+
+        // Builds are only kept for the revision being built
+        let build_garbage = revision_garbage(
+            &module.build_path,
+            &flavour_dirs,
+            |revision, _| revision != module.revision,
+        );
+
+        // Build directories which are still in use, but have not been touched
+        // for a while
+        for (_, rev_path) in revision_dirs(&module.build_path) {
+            for (_, path) in subdirs(&rev_path) {
+                let collected =
+                    build_garbage.iter().any(|g| path.starts_with(&g.path));
+
+                let expired = last_modified(&path)
+                    .and_then(|modified| modified.elapsed().ok())
+                    .is_some_and(|age| age > max_age);
+
+                if !collected && expired {
+                    garbage.push(Garbage {
+                        path,
+                        reason: format!(
+                            "build not modified for over {} days",
+                            max_age.as_secs() / 86400
+                        ),
+                    });
+                }
+            }
+        }
+
+        garbage.extend(build_garbage);
+    }
+
+    Ok(garbage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{module::Dependency, testing};
+
+    fn paths(garbage: &[Garbage], root: &Path) -> Vec<String> {
+        let mut paths: Vec<String> = garbage
+            .iter()
+            .map(|g| {
+                g.path.strip_prefix(root).unwrap().to_string_lossy().to_string()
+            })
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn revision_garbage_keeps_unsuperseded_flavours() {
+        let root = testing::temp_dir("revision-garbage");
+        for dir in ["1/gcc-12", "1/gcc-13", "1/old", "2/gcc-13", "3/old"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        let flavour_dirs = vec!["gcc-12".to_string(), "gcc-13".to_string()];
+        let path = root.to_str().unwrap();
+
+        // Only gcc 13 has a newer revision, so revision 1 is still needed
+        let superseded =
+            |revision, name: &str| revision == 1 && name == "gcc-13";
+        let garbage = revision_garbage(path, &flavour_dirs, superseded);
+        assert_eq!(paths(&garbage, &root), vec!["1/old", "3"]);
+
+        let garbage =
+            revision_garbage(path, &flavour_dirs, |revision, _| revision == 1);
+        assert_eq!(paths(&garbage, &root), vec!["1", "3"]);
+        assert_eq!(
+            garbage[0].reason,
+            "revision 1 is superseded for every flavour"
+        );
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn garbage_keeps_only_copy_of_a_flavour() {
+        let dir = testing::temp_dir("garbage");
+        let root = dir.to_str().unwrap();
+        let config = testing::config(root);
+
+        let mut modules = vec![
+            testing::module(root, "compiler/gcc/12", Vec::new()),
+            testing::module(root, "compiler/gcc/13", Vec::new()),
+        ];
+        let app = testing::module(
+            root,
+            "apps/app/1.0",
+            vec![Dependency::Class("compiler".to_string())],
+        );
+        modules.push(app.clone());
+
+        let all = flavours::generate_with(&app, &modules).unwrap();
+        let (gcc12, gcc13) = (&all[0], &all[1]);
+
+        // gcc 12 is only installed at revision 1, gcc 13 at 1 and 2
+        let mut records = vec![
+            testing::record(&app, gcc12, 1),
+            testing::record(&app, gcc13, 1),
+            testing::record(&app, gcc13, 2),
+        ];
+        std::fs::create_dir_all(dir.join("install/apps/unknown-1.0")).unwrap();
+        std::fs::create_dir_all(dir.join("install/other/thing-1.0")).unwrap();
+
+        let max_age = Duration::from_secs(86400);
+        let collected = garbage(&modules, &records, &config, max_age).unwrap();
+        assert_eq!(paths(&collected, &dir), vec!["install/apps/unknown-1.0"]);
+
+        // Once gcc 12 is installed at revision 2, revision 1 can go
+        records.push(testing::record(&app, gcc12, 2));
+        let collected = garbage(&modules, &records, &config, max_age).unwrap();
+        assert_eq!(
+            paths(&collected, &dir),
+            vec!["install/apps/app-1.0/1", "install/apps/unknown-1.0"]
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn protected_roots() {
+        let dir = testing::temp_dir("protected");
+        let root = dir.to_str().unwrap();
+
+        // The build root is nested inside the install root
+        let config = config::Config {
+            build_root: format!("{root}/install/build"),
+            ..testing::config(root)
+        };
+        std::fs::create_dir_all(&config.build_root).unwrap();
+        std::fs::create_dir_all(dir.join("install/apps/app-1.0")).unwrap();
+
+        let protected = protected_paths(&config);
+        assert_eq!(
+            protected,
+            vec![
+                dir.join("install/build").canonicalize().unwrap(),
+                dir.join("install").canonicalize().unwrap(),
+            ]
+        );
+
+        assert!(contains_protected(&dir.join("install"), &protected));
+        assert!(contains_protected(&dir, &protected));
+        assert!(!contains_protected(
+            &dir.join("install/apps/app-1.0"),
+            &protected
+        ));
+
+        // Paths which cannot be resolved are never removed
+        assert!(contains_protected(&dir.join("missing"), &protected));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -2,7 +2,7 @@ use std::{fs, path::Path, process::Command};
 
 use pyo3::prelude::*;
 
-use crate::{archive, file_manager, log, shell::Shell};
+use crate::{archive, file_manager, hash, log, shell::Shell};
 
 const FILE_NAME: &str = "curl_download_result";
 
@@ -30,6 +30,15 @@ pub trait DownloaderImpl: Sized + Clone {
     /// The function will return [`Err::<String>`], where the [`String`]
     /// contains an appropriate error message.
     fn download<P: AsRef<Path>>(&self, path: &P) -> Result<(), String>;
+
+    /// Identify the exact source code downloaded into `path`, such as a commit
+    /// hash or checksum. Returns [`None`] if this cannot be determined.
+    fn source_revision<P: AsRef<Path>>(&self, path: &P) -> Option<String>;
+
+    /// Serialise the settings which affect what is downloaded, with
+    /// [`hash::canonical`], for the fingerprint of a module. Changing the
+    /// result changes the revision of every module using this downloader.
+    fn fingerprint(&self) -> String;
 }
 
 #[derive(Debug, Clone)]
@@ -278,6 +287,31 @@ impl DownloaderImpl for GitClone {
 
         Ok(())
     }
+
+    fn source_revision<P: AsRef<Path>>(&self, path: &P) -> Option<String> {
+        let output = Command::new("git")
+            .current_dir(path)
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()?;
+
+        if output.status.success() {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            None
+        }
+    }
+
+    fn fingerprint(&self) -> String {
+        // A shallow clone checks out the same source
+        hash::canonical(&[
+            ("url", self.url.clone()),
+            ("branch", hash::canonical_option(self.branch.as_ref())),
+            ("commit", hash::canonical_option(self.commit.as_ref())),
+            ("submodules", self.submodules.to_string()),
+            ("patches", hash::canonical_list(self.patches.as_ref())),
+        ])
+    }
 }
 
 impl Curl {
@@ -351,6 +385,21 @@ impl DownloaderImpl for Curl {
 
         Ok(())
     }
+
+    fn source_revision<P: AsRef<Path>>(&self, _path: &P) -> Option<String> {
+        Some(self.sha256.as_ref().map_or_else(
+            || self.url.clone(),
+            |sha256| format!("sha256:{sha256}"),
+        ))
+    }
+
+    fn fingerprint(&self) -> String {
+        hash::canonical(&[
+            ("url", self.url.clone()),
+            ("sha256", hash::canonical_option(self.sha256.as_ref())),
+            ("archive", hash::canonical_option(self.archive.as_ref())),
+        ])
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -376,4 +425,23 @@ impl DownloaderImpl for Downloader {
             Self::Curl(curl) => curl.download(path),
         }
     }
+
+    fn source_revision<P: AsRef<Path>>(&self, path: &P) -> Option<String> {
+        match self {
+            Self::GitClone(clone) => clone.source_revision(path),
+            Self::Curl(curl) => curl.source_revision(path),
+        }
+    }
+
+    fn fingerprint(&self) -> String {
+        let (kind, settings) = match self {
+            Self::GitClone(clone) => ("git", clone.fingerprint()),
+            Self::Curl(curl) => ("curl", curl.fingerprint()),
+        };
+
+        hash::canonical(&[
+            ("downloader", kind.to_string()),
+            ("settings", settings),
+        ])
+    }
 }
@@ -1,3 +1,10 @@
+use std::path::Path;
+
+use crate::{
+    database::{self, InstallRecord},
+    module::{Environment, Module},
+};
+
 /// Read a colon-separated environment variable and return a vector of strings
 ///
 /// If only one string is present, it is returned as a single-element vector.
@@ -14,3 +21,133 @@ pub fn read_var(name: &str) -> Option<Vec<String>> {
             .collect(),
     )
 }
+
+/// Standard subdirectories of an install prefix and the variable each is
+/// prepended to. An empty path refers to the prefix itself.
+const PREFIX_LAYOUT: [(&str, &str, &str); 11] = [
+    ("bin", "PATH", "bin"),
+    ("lib", "LD_LIBRARY_PATH", "lib"),
+    ("lib64", "LD_LIBRARY_PATH", "lib64"),
+    ("include", "CPATH", "include"),
+    ("lib/pkgconfig", "PKG_CONFIG_PATH", "lib/pkgconfig"),
+    ("lib64/pkgconfig", "PKG_CONFIG_PATH", "lib64/pkgconfig"),
+    ("share/pkgconfig", "PKG_CONFIG_PATH", "share/pkgconfig"),
+    ("share/man", "MANPATH", "share/man"),
+    ("lib/cmake", "CMAKE_PREFIX_PATH", ""),
+    ("lib64/cmake", "CMAKE_PREFIX_PATH", ""),
+    ("share/cmake", "CMAKE_PREFIX_PATH", ""),
+];
+
+/// Find the Python `site-packages` directories below `prefix`, relative to
+/// it, e.g. `lib/python3.12/site-packages`
+fn site_packages(prefix: &Path) -> Vec<String> {
+    let mut dirs: Vec<String> = ["lib", "lib64"]
+        .iter()
+        .flat_map(|lib| {
+            std::fs::read_dir(prefix.join(lib))
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .filter_map(|entry| {
+                            let name = entry.file_name().into_string().ok()?;
+                            let site = format!("{lib}/{name}/site-packages");
+
+                            (name.starts_with("python")
+                                && prefix.join(&site).is_dir())
+                            .then_some(site)
+                        })
+                        .collect::<Vec<String>>()
+                })
+                .unwrap_or_default()
+        })
+        .collect();
+
+    dirs.sort();
+    dirs
+}
+
+/// Inspect an install prefix and list the paths to prepend to each variable
+/// for the software installed there to be usable, as `(variable, path)` pairs
+/// with paths relative to `prefix`.
+///
+/// ```
+/// let prefix = std::env::temp_dir().join("sccmod-detect-doctest");
+/// std::fs::create_dir_all(prefix.join("bin")).unwrap();
+/// std::fs::create_dir_all(prefix.join("lib/cmake")).unwrap();
+///
+/// let detected = sccmod::environment::detect(&prefix);
+/// assert!(detected.contains(&("PATH".to_string(), "bin".to_string())));
+/// assert!(detected.contains(&("CMAKE_PREFIX_PATH".to_string(), String::new())));
+/// assert!(!detected.iter().any(|(var, _)| var == "CPATH"));
+/// # std::fs::remove_dir_all(&prefix).unwrap();
+/// ```
+pub fn detect<P: AsRef<Path>>(prefix: &P) -> Vec<(String, String)> {
+    let prefix = prefix.as_ref();
+
+    let mut detected: Vec<(String, String)> = PREFIX_LAYOUT
+        .iter()
+        .filter(|(dir, _, _)| prefix.join(dir).is_dir())
+        .map(|(_, var, path)| ((*var).to_string(), (*path).to_string()))
+        .collect();
+
+    detected.extend(
+        site_packages(prefix)
+            .into_iter()
+            .map(|path| ("PYTHONPATH".to_string(), path)),
+    );
+
+    detected.dedup();
+    detected
+}
+
+/// Merge detected `(variable, path)` pairs into an explicit environment.
+///
+/// Detected paths come first, so explicit prepends take precedence, and paths
+/// the explicit environment already uses for the same variable are skipped.
+#[must_use]
+pub fn merge(
+    explicit: &[(String, Environment)],
+    detected: &[(String, String)],
+) -> Vec<(String, Environment)> {
+    let declared = |var: &str, path: &str| {
+        explicit
+            .iter()
+            .any(|(key, value)| key == var && value.value() == Some(path))
+    };
+
+    let mut merged: Vec<(String, Environment)> = Vec::new();
+    for (var, path) in detected {
+        let seen = merged.iter().any(|(key, value)| {
+            key == var
+                && matches!(value, Environment::Prepend(val, _) if val == path)
+        });
+
+        if !seen && !declared(var, path) {
+            merged
+                .push((var.clone(), Environment::Prepend(path.clone(), None)));
+        }
+    }
+
+    merged.extend(explicit.iter().cloned());
+    merged
+}
+
+/// The environment of `module` as written to its modulefiles.
+///
+/// The explicit environment is merged with the paths detected in the newest
+/// installed revision of each flavour. `flavour` restricts detection to a
+/// single flavour.
+#[must_use]
+pub fn resolve(
+    module: &Module,
+    records: &[InstallRecord],
+    flavour: Option<&str>,
+) -> Vec<(String, Environment)> {
+    let detected: Vec<(String, String)> = database::latest(records, module)
+        .into_iter()
+        .filter(|rec| flavour.is_none_or(|name| rec.flavour == name))
+        .flat_map(|rec| rec.environment.iter().cloned())
+        .collect();
+
+    merge(&module.environment, &detected)
+}
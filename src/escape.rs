@@ -0,0 +1,145 @@
+/// Escape `value` for use inside a double-quoted Tcl string.
+///
+/// Substitutions are disabled, and braces are escaped so the string can also
+/// appear inside a braced body, such as that of `ModulesHelp`.
+///
+/// ```
+/// use sccmod::escape::tcl_quoted;
+///
+/// assert_eq!(tcl_quoted(r#"say "hi" $USER [x] {y}"#), r#"say \"hi\" \$USER \[x\] \{y\}"#);
+/// ```
+#[must_use]
+pub fn tcl_quoted(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '\\' | '"' | '$' | '[' | ']' | '{' | '}' => format!("\\{c}"),
+            _ => c.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+/// Escape `value` for use as a single bare Tcl word
+///
+/// ```
+/// use sccmod::escape::tcl_word;
+///
+/// assert_eq!(tcl_word("/opt/my apps;x"), r"/opt/my\ apps\;x");
+/// ```
+#[must_use]
+pub fn tcl_word(value: &str) -> String {
+    tcl_quoted(value)
+        .chars()
+        .map(|c| match c {
+            ' ' | ';' => format!("\\{c}"),
+            '\t' => "\\t".to_string(),
+            '\n' => "\\n".to_string(),
+            _ => c.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+/// Escape `value` for use in a comment starting with `prefix`, such as `# `:
+/// every line of `value` becomes a comment, and a trailing backslash cannot
+/// continue the comment onto the next line.
+#[must_use]
+pub fn comment(value: &str, prefix: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', &format!("\n{prefix}"))
+}
+
+/// Escape `value` for use inside a double-quoted Lua string
+///
+/// ```
+/// use sccmod::escape::lua_quoted;
+///
+/// assert_eq!(lua_quoted("a \"b\"\n\\c"), r#"a \"b\"\n\\c"#);
+/// ```
+#[must_use]
+pub fn lua_quoted(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '\\' | '"' => format!("\\{c}"),
+            '\n' => "\\n".to_string(),
+            '\r' => "\\r".to_string(),
+            _ => c.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+/// Escape `value` for use inside a Lua long string, `[[...]]`, which has no
+/// escape sequences: any `]]` which would end the string early is split.
+#[must_use]
+pub fn lua_long(value: &str) -> String {
+    let mut value = value.to_string();
+
+    while value.contains("]]") {
+        value = value.replace("]]", "] ]");
+    }
+
+    value
+}
+
+/// Escape `value` for use inside a double-quoted cmake argument, keeping it a
+/// single list element
+///
+/// ```
+/// use sccmod::escape::cmake_quoted;
+///
+/// assert_eq!(cmake_quoted(r#"/opt/a;b/"c"/${x}"#), r#"/opt/a\;b/\"c\"/\${x}"#);
+/// ```
+#[must_use]
+pub fn cmake_quoted(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '\\' | '"' | '$' | ';' => format!("\\{c}"),
+            _ => c.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+/// Quote `value` as a single word for bash and zsh
+///
+/// ```
+/// use sccmod::escape::sh_quoted;
+///
+/// assert_eq!(sh_quoted("it's $HOME"), r"'it'\''s $HOME'");
+/// ```
+#[must_use]
+pub fn sh_quoted(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quote `value` as a single word for fish
+///
+/// ```
+/// use sccmod::escape::fish_quoted;
+///
+/// assert_eq!(fish_quoted(r"it's a\b"), r"'it\'s a\\b'");
+/// ```
+#[must_use]
+pub fn fish_quoted(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// Quote `value` as a single word for csh and tcsh, where history
+/// substitution applies even inside single quotes and a newline must be
+/// escaped to be kept
+///
+/// ```
+/// use sccmod::escape::csh_quoted;
+///
+/// assert_eq!(csh_quoted("it's loud!"), r"'it'\''s loud\!'");
+/// ```
+#[must_use]
+pub fn csh_quoted(value: &str) -> String {
+    format!(
+        "'{}'",
+        value.replace('\'', r"'\''").replace('!', r"\!").replace('\n', "\\\n")
+    )
+}
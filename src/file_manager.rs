@@ -85,3 +85,95 @@ pub fn absolute_path<P: AsRef<Path>>(path: &P) -> Option<PathBuf> {
         None
     }
 }
+
+/// The total size in bytes of all files below `path`. Symbolic links are not
+/// followed. Missing or unreadable paths count as empty.
+pub fn dir_size<P: AsRef<Path>>(path: &P) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    std::fs::read_dir(path).map_or(0, |entries| {
+        entries.flatten().map(|entry| dir_size(&entry.path())).sum()
+    })
+}
+
+/// Format a size in bytes for humans.
+///
+/// # Example
+/// ```rust
+/// use sccmod::file_manager::format_size;
+///
+/// assert_eq!(format_size(512), "512 B");
+/// assert_eq!(format_size(1536), "1.5 KiB");
+/// assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+/// ```
+#[must_use]
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    // Sizes are only printed, so the loss of precision is irrelevant
+    #[allow(clippy::cast_precision_loss)]
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Remove `path` and everything below it. Missing paths are ignored.
+///
+/// # Errors
+/// Errors if the path exists but cannot be removed.
+pub fn remove_path<P: AsRef<Path>>(path: &P) -> Result<(), String> {
+    let path = path.as_ref();
+
+    let result = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(_) => return Ok(()),
+    };
+
+    result.map_err(|err| format!("Failed to remove {}: {err}", path.display()))
+}
+
+/// Replace the contents of `path` with `content` atomically: the content is
+/// written to a temporary file next to `path`, which is then renamed over it,
+/// so readers never see a partially written file.
+///
+/// # Errors
+/// Errors if the temporary file cannot be written or renamed.
+pub fn write_atomic<P: AsRef<Path>>(
+    path: &P,
+    content: &str,
+) -> Result<(), String> {
+    let path = path.as_ref();
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Invalid file path {}", path.display()))?;
+    let temporary = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    std::fs::write(&temporary, content)
+        .and_then(|()| std::fs::rename(&temporary, path))
+        .map_err(|err| {
+            let _ = std::fs::remove_file(&temporary);
+            format!("Failed to write {}: {err}", path.display())
+        })
+}
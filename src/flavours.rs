@@ -1,73 +1,406 @@
-use crate::module::{get_modules, Dependency, Module};
+use std::collections::HashMap;
 
-pub fn generate(module: &Module) -> Result<Vec<(Vec<Module>, usize)>, String> {
-    println!("{module:?}");
+use crate::{
+    config, log,
+    module::{Dependency, Module},
+    version::{split_spec, Version},
+};
 
-    let modules = get_modules()?;
+/// Restricts which flavour permutations of a module are built.
+///
+/// Each class may have a list of partials (e.g. `gcc/13`) and a permutation is
+/// selected only if, for every class with a list, its module of that class
+/// contains one of the partials in its identifier. Classes without a list are
+/// unrestricted.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub allowed: HashMap<String, Vec<String>>,
+}
 
-    for module in &modules {
-        println!("{} => {}", module.identifier(), module.mod_name());
-    }
+impl Selection {
+    /// Create a selection from the `default_flavours` in the config, with any
+    /// classes mentioned in `partials` (a comma-separated list, as passed to
+    /// `--flavour`) overriding the defaults.
+    ///
+    /// # Errors
+    /// Errors if a partial does not match any module.
+    pub fn new(
+        config: &config::Config,
+        partials: Option<&str>,
+        modules: &[Module],
+    ) -> Result<Self, String> {
+        let mut allowed = config.default_flavours.clone();
+        let mut overridden = Vec::new();
 
-    // 1. Extract dependent modules and classes
-    let required_modules: Vec<&Module> = module
-        .dependencies
-        .iter()
-        .filter_map(|dep| {
-            // if let Dependency::Module(name) = dep {
-            //     Some(name)
-            // } else {
-            //     None
-            // }
-
-            match dep {
-                Dependency::Module(name) | Dependency::Depends(name) => {
-                    Some(name)
+        for partial in partials
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        {
+            let mut classes: Vec<&String> = modules
+                .iter()
+                .filter(|m| contains_partial(m, partial))
+                .map(|m| &m.class)
+                .collect();
+            classes.sort();
+            classes.dedup();
+
+            if classes.is_empty() {
+                return Err(format!(
+                    "No module matches the flavour '{partial}'"
+                ));
+            }
+
+            for class in classes {
+                if !overridden.contains(class) {
+                    allowed.insert(class.clone(), Vec::new());
+                    overridden.push(class.clone());
                 }
-                _ => None,
+
+                allowed.entry(class.clone()).or_default().push(partial.into());
             }
+        }
+
+        Ok(Self { allowed })
+    }
+
+    /// Returns `true` if the permutation `flavour` is selected
+    #[must_use]
+    pub fn matches(&self, flavour: &(Vec<Module>, usize)) -> bool {
+        flavour.0[..flavour.1].iter().all(|module| {
+            self.allowed.get(&module.class).is_none_or(|partials| {
+                partials.iter().any(|p| contains_partial(module, p))
+            })
         })
-        .map(|name| {
-            modules
+    }
+
+    /// This selection without the restrictions on classes other than
+    /// `classes`
+    #[must_use]
+    pub fn restricted_to(&self, classes: &[String]) -> Self {
+        Self {
+            allowed: self
+                .allowed
                 .iter()
-                .find(|m| (&m.identifier() == name) || (&m.mod_name() == name))
-                .ok_or_else(|| {
-                    format!(
-                        "Failed to find module matching dependency '{name}'"
-                    )
-                })
-        })
-        .collect::<Result<Vec<&Module>, String>>()?;
+                .filter(|(class, _)| classes.contains(class))
+                .map(|(class, partials)| (class.clone(), partials.clone()))
+                .collect(),
+        }
+    }
+
+    /// Remove every permutation which is not selected
+    #[must_use]
+    pub fn filter(
+        &self,
+        flavours: Vec<(Vec<Module>, usize)>,
+    ) -> Vec<(Vec<Module>, usize)> {
+        flavours.into_iter().filter(|flav| self.matches(flav)).collect()
+    }
+}
 
-    let required_classes: Vec<String> = module
+/// The classes of the modules `module` has flavours of: those it depends on
+/// through a `Class` dependency, and those of the providers of its `Virtual`
+/// dependencies among `modules`
+#[must_use]
+pub fn classes(module: &Module, modules: &[Module]) -> Vec<String> {
+    let mut classes: Vec<String> = module
         .dependencies
         .iter()
-        .filter_map(|dep| {
-            if let Dependency::Class(name) = dep {
-                Some(name.to_owned())
-            } else {
-                None
-            }
+        .flat_map(|dep| match dep {
+            Dependency::Class(class) => vec![class.clone()],
+            Dependency::Virtual(name) => modules
+                .iter()
+                .filter(|m| m.provides.contains(name))
+                .map(|m| m.class.clone())
+                .collect(),
+            _ => Vec::new(),
         })
         .collect();
 
-    let deny_modules: Vec<Vec<String>> = module
-        .dependencies
+    classes.sort();
+    classes.dedup();
+    classes
+}
+
+fn contains_partial(module: &Module, partial: &str) -> bool {
+    module.identifier().to_lowercase().contains(&partial.to_lowercase())
+}
+
+/// Find the module matching a dependency specification.
+///
+/// A specification without a version requirement must match a module's
+/// [`Module::identifier`] or [`Module::mod_name`] exactly. With a requirement
+/// (e.g. `gcc@>=12,<14` or `libraries/hdf5@1.14.x`), the name must match a
+/// module's `name` or `class/name`, and the newest version satisfying the
+/// requirement is selected.
+///
+/// # Errors
+/// Errors if no module matches, listing every candidate that was rejected and
+/// the reason it was rejected.
+pub fn resolve_dependency<'a>(
+    modules: &'a [Module],
+    spec: &str,
+) -> Result<&'a Module, String> {
+    let (name, requirement) = split_spec(spec)?;
+
+    let Some(requirement) = requirement else {
+        return modules
+            .iter()
+            .find(|m| m.identifier() == name || m.mod_name() == name)
+            .ok_or_else(|| {
+                format!("Failed to find module matching dependency '{name}'")
+            });
+    };
+
+    let mut rejected = Vec::new();
+    let mut best: Option<(&Module, Version)> = None;
+
+    for module in modules
         .iter()
-        .filter_map(|dep| {
-            if let Dependency::Deny(name) = dep {
-                Some(name.split(':').map(|s| s.to_string()).collect())
-            } else {
-                None
+        .filter(|m| m.name == name || format!("{}/{}", m.class, m.name) == name)
+    {
+        match module.version.parse::<Version>() {
+            Ok(version) if requirement.matches(&version) => {
+                if best.as_ref().is_none_or(|(_, current)| &version > current) {
+                    best = Some((module, version));
+                }
+            }
+            Ok(_) => rejected.push(format!(
+                "{}: version does not satisfy '{requirement}'",
+                module.identifier()
+            )),
+            Err(err) => {
+                rejected.push(format!("{}: {err}", module.identifier()));
             }
+        }
+    }
+
+    best.map(|(module, _)| module).ok_or_else(|| {
+        if rejected.is_empty() {
+            format!("Failed to find any module named '{name}' (from '{spec}')")
+        } else {
+            format!(
+                "No module satisfies dependency '{spec}'. Rejected candidates:\n  {}",
+                rejected.join("\n  ")
+            )
+        }
+    })
+}
+
+/// Returns `true` if `module` is matched by `spec`. A specification may be a
+/// module identifier, `name/version`, a bare `name` or `class/name`, or a
+/// name with a version requirement such as `gcc@>=12`.
+#[must_use]
+pub fn matches_spec(module: &Module, spec: &str) -> bool {
+    let names_match = |name: &str| {
+        module.name == name
+            || format!("{}/{}", module.class, module.name) == name
+    };
+
+    match split_spec(spec) {
+        Ok((name, Some(requirement))) => {
+            names_match(name)
+                && module
+                    .version
+                    .parse::<Version>()
+                    .is_ok_and(|version| requirement.matches(&version))
+        }
+        Ok((name, None)) => {
+            names_match(name)
+                || module.mod_name() == name
+                || module.identifier() == name
+        }
+        Err(_) => false,
+    }
+}
+
+/// A flavour rule restricting which permutations are generated
+enum Rule {
+    /// Drop permutations containing all of these modules, named exactly as
+    /// `name/version`
+    Deny(Vec<String>),
+
+    /// Drop permutations containing none of these module specifications
+    Require(Vec<String>),
+
+    /// Drop permutations containing the first module, but none of the others
+    OnlyWith(String, Vec<String>),
+}
+
+impl Rule {
+    fn from_dependency(dep: &Dependency) -> Option<Self> {
+        let split = |names: &str| names.split(':').map(String::from).collect();
+
+        match dep {
+            Dependency::Deny(names) => Some(Self::Deny(split(names))),
+            Dependency::Require(names) => Some(Self::Require(split(names))),
+            Dependency::OnlyWith(name, partners) => {
+                Some(Self::OnlyWith(name.clone(), split(partners)))
+            }
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Self::Deny(names) => format!("Deny({})", names.join(", ")),
+            Self::Require(names) => format!("Require({})", names.join(" | ")),
+            Self::OnlyWith(name, partners) => {
+                format!("OnlyWith({name} -> {})", partners.join(" | "))
+            }
+        }
+    }
+
+    /// Evaluate the rule against a permutation, returning whether it is
+    /// satisfied and a short explanation
+    fn evaluate(&self, perm: &[Module]) -> (bool, &'static str) {
+        let contains =
+            |spec: &String| perm.iter().any(|m| matches_spec(m, spec));
+        let present =
+            |name: &String| perm.iter().any(|m| &m.mod_name() == name);
+
+        match self {
+            Self::Deny(names) if names.iter().all(present) => {
+                (false, "denied combination present")
+            }
+            Self::Deny(_) => (true, "denied combination absent"),
+            Self::Require(names) if names.iter().any(contains) => {
+                (true, "required module present")
+            }
+            Self::Require(_) => (false, "required module missing"),
+            Self::OnlyWith(name, _) if !contains(name) => {
+                (true, "not applicable")
+            }
+            Self::OnlyWith(_, partners) if partners.iter().any(contains) => {
+                (true, "partner module present")
+            }
+            Self::OnlyWith(..) => (false, "partner module missing"),
+        }
+    }
+}
+
+/// A flavour permutation, along with how the flavour rules applied to it
+#[derive(Debug, Clone)]
+pub struct Permutation {
+    pub flavour: (Vec<Module>, usize),
+
+    /// The rule which dropped this permutation, if any
+    pub dropped_by: Option<String>,
+
+    /// The outcome of each flavour rule
+    pub trace: Vec<String>,
+}
+
+/// Generate the flavour permutations of `module`, selecting dependencies from
+/// the available `modules` and logging why each one was kept or dropped.
+///
+/// # Errors
+/// Errors if a dependency cannot be resolved.
+pub fn generate(
+    module: &Module,
+    modules: &[Module],
+) -> Result<Vec<(Vec<Module>, usize)>, String> {
+    let permutations = evaluate(module, modules)?;
+
+    for perm in &permutations {
+        let name = gen_name(&perm.flavour);
+        let trace = perm.trace.join("; ");
+
+        match &perm.dropped_by {
+            Some(rule) => log::info(&format!(
+                "Dropping flavour {name} (by {rule}) [{trace}]"
+            )),
+            None if trace.is_empty() => {
+                log::info(&format!("Keeping flavour {name}"));
+            }
+            None => log::info(&format!("Keeping flavour {name} [{trace}]")),
+        }
+    }
+
+    Ok(permutations
+        .into_iter()
+        .filter(|perm| perm.dropped_by.is_none())
+        .map(|perm| perm.flavour)
+        .collect())
+}
+
+/// Generate the flavour permutations of `module`, selecting dependencies from
+/// an already-loaded list of `modules`.
+///
+/// # Errors
+/// Errors if a dependency cannot be resolved.
+pub fn generate_with(
+    module: &Module,
+    modules: &[Module],
+) -> Result<Vec<(Vec<Module>, usize)>, String> {
+    Ok(evaluate(module, modules)?
+        .into_iter()
+        .filter(|perm| perm.dropped_by.is_none())
+        .map(|perm| perm.flavour)
+        .collect())
+}
+
+/// Generate every flavour permutation of `module`, including those dropped by
+/// its `Deny`, `Require` and `OnlyWith` rules, selecting dependencies from an
+/// already-loaded list of `modules`.
+///
+/// # Errors
+/// Errors if a dependency cannot be resolved.
+pub fn evaluate(
+    module: &Module,
+    modules: &[Module],
+) -> Result<Vec<Permutation>, String> {
+    // 1. Extract dependent modules and classes
+    let required_modules: Vec<&Module> = module
+        .dependencies
+        .iter()
+        .filter_map(|dep| match dep {
+            Dependency::Module(name) | Dependency::Depends(name) => Some(name),
+            _ => None,
+        })
+        .map(|name| resolve_dependency(modules, name))
+        .collect::<Result<Vec<&Module>, String>>()?;
+
+    // Classes and virtuals are both expanded across all of their members
+    let required_classes: Vec<&Dependency> = module
+        .dependencies
+        .iter()
+        .filter(|dep| {
+            matches!(dep, Dependency::Class(_) | Dependency::Virtual(_))
         })
         .collect();
 
-    // 2. Extract modules with matching class
+    let rules: Vec<Rule> =
+        module.dependencies.iter().filter_map(Rule::from_dependency).collect();
+
+    // 2. Extract modules with matching class, or providing the virtual
     let available_per_class: Vec<Vec<&Module>> = required_classes
         .iter()
-        .map(|class| modules.iter().filter(|m| &m.class == class).collect())
-        .collect();
+        .map(|dep| {
+            let available: Vec<&Module> = match dep {
+                Dependency::Class(class) => {
+                    modules.iter().filter(|m| &m.class == class).collect()
+                }
+                Dependency::Virtual(name) => modules
+                    .iter()
+                    .filter(|m| m.provides.contains(name))
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            if available.is_empty() {
+                Err(match dep {
+                    Dependency::Virtual(name) => {
+                        format!("No modules provide virtual '{name}'")
+                    }
+                    _ => format!("No modules match dependency '{dep:?}'"),
+                })
+            } else {
+                Ok(available)
+            }
+        })
+        .collect::<Result<_, String>>()?;
 
     // 3. Generate permutations of the classes
     let mut index = vec![0usize; required_classes.len() + 1];
@@ -86,28 +419,24 @@ pub fn generate(module: &Module) -> Result<Vec<(Vec<Module>, usize)>, String> {
         // Add pre-defined modules and submodules
         perm.extend(required_modules.iter().map(|&m| m.to_owned()));
 
-        let permutation = (perm, required_classes.len());
-        // (perm, required_classes.len() + module.submodules.len());
+        // 4. Apply the flavour rules, recording the first one to fail
+        let mut dropped_by = None;
+        let mut trace = Vec::new();
+        for rule in &rules {
+            let (satisfied, reason) = rule.evaluate(&perm);
+            trace.push(format!("{}: {reason}", rule.name()));
 
-        // If the permutation contains a denied module, do not include it
-        if !deny_modules.iter().any(|deny| {
-            // permutation
-            //     .0
-            //     .iter()
-            //     .map(|m| m.mod_name())
-            //     .any(|name| &name == deny)
-
-            deny.iter().all(|deny_mod| {
-                permutation
-                    .0
-                    .iter()
-                    .map(|m| m.mod_name())
-                    .any(|mod_name| &mod_name == deny_mod)
-            })
-        }) {
-            permutations.push(permutation);
+            if !satisfied && dropped_by.is_none() {
+                dropped_by = Some(rule.name());
+            }
         }
 
+        permutations.push(Permutation {
+            flavour: (perm, required_classes.len()),
+            dropped_by,
+            trace,
+        });
+
         index[0] += 1;
 
         let mut i = 0;
@@ -129,3 +458,212 @@ pub fn gen_name(flav: &(Vec<Module>, usize)) -> String {
     }
     flav_str
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn compilers() -> Vec<Module> {
+        ["compiler/gcc/12.3", "compiler/gcc/13.1", "compiler/intel/2024"]
+            .into_iter()
+            .chain(["mpi/openmpi/4.1", "mpi/openmpi/5.0"])
+            .map(|id| testing::module("/sc", id, Vec::new()))
+            .collect()
+    }
+
+    fn flavour(modules: &[Module], ids: &[&str]) -> (Vec<Module>, usize) {
+        let flav: Vec<Module> = ids
+            .iter()
+            .map(|id| {
+                modules.iter().find(|m| &m.identifier() == id).unwrap().clone()
+            })
+            .collect();
+        let len = flav.len();
+        (flav, len)
+    }
+
+    #[test]
+    fn selection_merges_defaults_and_partials() {
+        let modules = compilers();
+        let mut config = testing::config("/sc");
+        config.default_flavours = HashMap::from([
+            ("compiler".to_string(), vec!["gcc/13".to_string()]),
+            ("mpi".to_string(), vec!["openmpi/4".to_string()]),
+        ]);
+
+        // Without partials the configured defaults apply
+        let selection = Selection::new(&config, None, &modules).unwrap();
+        let ids = |flav: &(Vec<Module>, usize)| -> Vec<String> {
+            flav.0.iter().map(Module::identifier).collect()
+        };
+        let all: Vec<_> = ["compiler/gcc/12.3", "compiler/gcc/13.1"]
+            .iter()
+            .flat_map(|cc| {
+                ["mpi/openmpi/4.1", "mpi/openmpi/5.0"]
+                    .map(|mpi| flavour(&modules, &[cc, mpi]))
+            })
+            .collect();
+        let selected: Vec<_> =
+            selection.filter(all.clone()).iter().map(ids).collect();
+        assert_eq!(selected, [["compiler/gcc/13.1", "mpi/openmpi/4.1"]]);
+
+        // A partial overrides the defaults of its own class only
+        let selection =
+            Selection::new(&config, Some("GCC/12, openmpi/5"), &modules)
+                .unwrap();
+        let selected: Vec<_> =
+            selection.filter(all.clone()).iter().map(ids).collect();
+        assert_eq!(selected, [["compiler/gcc/12.3", "mpi/openmpi/5.0"]]);
+
+        let selection =
+            Selection::new(&config, Some("gcc/12,gcc/13"), &modules).unwrap();
+        let selected: Vec<_> = selection.filter(all).iter().map(ids).collect();
+        assert_eq!(
+            selected,
+            [
+                ["compiler/gcc/12.3", "mpi/openmpi/4.1"],
+                ["compiler/gcc/13.1", "mpi/openmpi/4.1"]
+            ]
+        );
+
+        // Classes without a list are unrestricted
+        let selection =
+            Selection::new(&testing::config("/sc"), Some("intel"), &modules)
+                .unwrap();
+        assert!(selection.matches(&flavour(
+            &modules,
+            &["compiler/intel/2024", "mpi/openmpi/5.0"]
+        )));
+        assert!(!selection.matches(&flavour(&modules, &["compiler/gcc/13.1"])));
+
+        assert_eq!(
+            Selection::new(&config, Some("clang"), &modules).unwrap_err(),
+            "No module matches the flavour 'clang'"
+        );
+    }
+
+    #[test]
+    fn resolve_dependency_picks_newest_match() {
+        let mut modules = compilers();
+        modules.push(testing::module("/sc", "compiler/gcc/trunk", Vec::new()));
+
+        let id =
+            |spec| resolve_dependency(&modules, spec).map(Module::identifier);
+        assert_eq!(id("gcc@>=12").unwrap(), "compiler/gcc/13.1");
+        assert_eq!(id("compiler/gcc@<13").unwrap(), "compiler/gcc/12.3");
+        assert_eq!(id("gcc/12.3").unwrap(), "compiler/gcc/12.3");
+
+        let err = id("gcc@>=14").unwrap_err();
+        assert!(err.starts_with(
+            "No module satisfies dependency 'gcc@>=14'. Rejected candidates:\n  "
+        ));
+        assert!(err.contains(
+            "\n  compiler/gcc/12.3: version does not satisfy '>=14'"
+        ));
+        assert!(err.contains(
+            "\n  compiler/gcc/13.1: version does not satisfy '>=14'"
+        ));
+        assert!(err.contains("\n  compiler/gcc/trunk: "));
+
+        assert_eq!(
+            id("clang@>=1").unwrap_err(),
+            "Failed to find any module named 'clang' (from 'clang@>=1')"
+        );
+    }
+
+    fn rule(dep: Dependency) -> Rule {
+        Rule::from_dependency(&dep).unwrap()
+    }
+
+    #[test]
+    fn deny_matches_names_exactly() {
+        let gcc = testing::module("/sc", "compiler/gcc/13.1", Vec::new());
+        let mpi = testing::module("/sc", "mpi/openmpi/5.0", Vec::new());
+        let perm = vec![gcc, mpi];
+
+        let deny = rule(Dependency::Deny("gcc/13.1:openmpi/5.0".into()));
+        assert_eq!(deny.evaluate(&perm), (false, "denied combination present"));
+        assert_eq!(
+            deny.evaluate(&perm[..1]),
+            (true, "denied combination absent")
+        );
+
+        // Unlike `Require` and `OnlyWith`, a bare name is not a match
+        let deny = rule(Dependency::Deny("gcc".into()));
+        assert_eq!(deny.evaluate(&perm), (true, "denied combination absent"));
+    }
+
+    #[test]
+    fn require_accepts_any_spec() {
+        let gcc = testing::module("/sc", "compiler/gcc/13.1", Vec::new());
+        let nvhpc = testing::module("/sc", "compiler/nvhpc/24.1", Vec::new());
+
+        let require = rule(Dependency::Require("gcc@>=12:intel".into()));
+        assert_eq!(require.evaluate(&[gcc]), (true, "required module present"));
+        assert_eq!(
+            require.evaluate(&[nvhpc]),
+            (false, "required module missing")
+        );
+    }
+
+    #[test]
+    fn only_with_needs_a_partner() {
+        let intel = testing::module("/sc", "compiler/intel/2024", Vec::new());
+        let gcc = testing::module("/sc", "compiler/gcc/13.1", Vec::new());
+        let impi = testing::module("/sc", "mpi/intelmpi/2021", Vec::new());
+        let ompi = testing::module("/sc", "mpi/openmpi/5.0", Vec::new());
+
+        let only =
+            rule(Dependency::OnlyWith("intel".into(), "intelmpi".into()));
+        assert_eq!(
+            only.evaluate(&[intel.clone(), impi]),
+            (true, "partner module present")
+        );
+        assert_eq!(
+            only.evaluate(&[intel, ompi.clone()]),
+            (false, "partner module missing")
+        );
+        assert_eq!(only.evaluate(&[gcc, ompi]), (true, "not applicable"));
+    }
+
+    #[test]
+    fn evaluate_traces_every_rule() {
+        let modules = vec![
+            testing::module("/sc", "compiler/gcc/13.1", Vec::new()),
+            testing::module("/sc", "compiler/intel/2024", Vec::new()),
+        ];
+        let app = testing::module(
+            "/sc",
+            "apps/app/1.0",
+            vec![
+                Dependency::Class("compiler".into()),
+                Dependency::Require("gcc".into()),
+                Dependency::Deny("gcc/13.1".into()),
+            ],
+        );
+
+        let perms = evaluate(&app, &modules).unwrap();
+        let gcc = perms.iter().find(|p| p.flavour.0[0].name == "gcc").unwrap();
+        let intel =
+            perms.iter().find(|p| p.flavour.0[0].name == "intel").unwrap();
+
+        assert_eq!(gcc.dropped_by.as_deref(), Some("Deny(gcc/13.1)"));
+        assert_eq!(
+            gcc.trace,
+            [
+                "Require(gcc): required module present",
+                "Deny(gcc/13.1): denied combination present"
+            ]
+        );
+        assert_eq!(intel.dropped_by.as_deref(), Some("Require(gcc)"));
+        assert_eq!(
+            intel.trace,
+            [
+                "Require(gcc): required module missing",
+                "Deny(gcc/13.1): denied combination absent"
+            ]
+        );
+        assert!(generate_with(&app, &modules).unwrap().is_empty());
+    }
+}
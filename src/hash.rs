@@ -0,0 +1,74 @@
+use std::fmt::Write;
+
+/// Hash `data` with the 64-bit FNV-1a algorithm.
+///
+/// Unlike [`std::hash::DefaultHasher`], the result is stable between runs and
+/// Rust versions, so it can be written to disk and compared later.
+///
+/// # Example
+/// ```rust
+/// use sccmod::hash::fnv1a;
+///
+/// assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+/// assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+/// ```
+#[must_use]
+pub fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    data.iter().fold(OFFSET, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Serialise `fields` unambiguously for hashing. Each value is prefixed by its
+/// length, so no value can be mistaken for the start of another field.
+///
+/// Unlike the [`Debug`] output of a struct, the result only changes when the
+/// fields passed in do.
+///
+/// # Example
+/// ```rust
+/// use sccmod::hash::canonical;
+///
+/// assert_eq!(
+///     canonical(&[("url", "a=1".to_string()), ("tag", String::new())]),
+///     "url=3:a=1\ntag=0:\n"
+/// );
+/// ```
+#[must_use]
+pub fn canonical(fields: &[(&str, String)]) -> String {
+    fields.iter().fold(String::new(), |mut out, (key, value)| {
+        let _ = writeln!(out, "{key}={}:{value}", value.len());
+        out
+    })
+}
+
+/// Serialise an optional value for [`canonical`], distinguishing [`None`]
+/// from any string
+#[must_use]
+pub fn canonical_option(value: Option<&String>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| format!("+{value}"))
+}
+
+/// Serialise an optional list for [`canonical`], distinguishing [`None`] from
+/// any list
+#[must_use]
+pub fn canonical_list(items: Option<&Vec<String>>) -> String {
+    items.map_or_else(
+        || "-".to_string(),
+        |items| {
+            items
+                .iter()
+                .map(|item| format!("{}:{item}", item.len()))
+                .fold("+".to_string(), |list, item| list + &item)
+        },
+    )
+}
+
+/// Hash `data` with [`fnv1a`] and return the result as a hex string
+#[must_use]
+pub fn hex_digest(data: &str) -> String {
+    format!("{:016x}", fnv1a(data.as_bytes()))
+}
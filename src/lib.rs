@@ -8,18 +8,35 @@ pub mod builders {
     pub mod make;
 }
 
+pub mod activate;
 pub mod archive;
 pub mod callbacks;
 pub mod cli;
 pub mod config;
+pub mod database;
+pub mod defaults;
+pub mod dependencies;
+pub mod diff;
+pub mod disk;
 pub mod downloaders;
 pub mod environment;
+pub mod escape;
 pub mod file_manager;
 pub mod flavours;
+pub mod hash;
 pub mod info;
+pub mod lmod;
 pub mod log;
 pub mod module;
 pub mod module_resolver;
 pub mod modulefile;
+pub mod package_config;
+pub mod plain;
 pub mod python_interop;
 pub mod shell;
+pub mod status;
+pub mod template;
+#[cfg(test)]
+mod testing;
+pub mod validate;
+pub mod version;
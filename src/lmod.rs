@@ -0,0 +1,298 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config,
+    database::{self, InstallRecord},
+    environment,
+    escape::{comment, lua_long, lua_quoted},
+    file_manager::PATH_SEP,
+    flavours,
+    module::{Dependency, Environment, Module},
+    modulefile::{help, metadata_comments, providers},
+    template,
+};
+
+/// The hierarchy level below `modulefile_root` containing modules built
+/// against `members`, such as `compiler-mpi/gcc/13.1/openmpi/5.0.3`. Modules
+/// without flavours live in `Core`.
+fn level(members: &[&Module]) -> PathBuf {
+    if members.is_empty() {
+        return PathBuf::from("Core");
+    }
+
+    let classes: Vec<&str> = members.iter().map(|m| m.class.as_str()).collect();
+
+    members.iter().fold(PathBuf::from(classes.join("-")), |path, m| {
+        path.join(&m.name).join(&m.version)
+    })
+}
+
+/// The modules selecting a flavour, in the order of `module`'s dependencies
+fn members(flavour: &(Vec<Module>, usize)) -> Vec<&Module> {
+    flavour.0.iter().take(flavour.1).collect()
+}
+
+/// The path of the Lua modulefile for `flavour` of `module`
+#[must_use]
+pub fn path(
+    module: &Module,
+    flavour: &(Vec<Module>, usize),
+    config: &config::Config,
+) -> String {
+    let path = PathBuf::from(&config.modulefile_root)
+        .join(level(&members(flavour)))
+        .join(&module.name)
+        .join(format!("{}.lua", module.version));
+
+    path.to_string_lossy().to_string()
+}
+
+/// Returns `true` if any of `modules` is flavoured by `module`, in which case
+/// loading `module` must make them visible by extending `MODULEPATH`
+fn opens_level(module: &Module, modules: &[Module]) -> bool {
+    modules.iter().any(|m| {
+        m.dependencies.iter().any(|dep| match dep {
+            Dependency::Class(class) => class == &module.class,
+            Dependency::Virtual(name) => module.provides.contains(name),
+            _ => false,
+        })
+    })
+}
+
+/// Only one module of each class, and one provider of each virtual, may be
+/// loaded at once
+fn conflicts(
+    module: &Module,
+    modules: &[Module],
+    config: &config::Config,
+) -> String {
+    let mut conflicts: Vec<String> = Vec::new();
+    if !config.class_no_conflict.contains(&module.class) {
+        conflicts.push(format!("family(\"{}\")\n", lua_quoted(&module.class)));
+    }
+
+    for virtual_name in &module.provides {
+        let others: Vec<String> = providers(modules, virtual_name)
+            .into_iter()
+            .filter(|name| *name != module.name)
+            .map(|name| format!("\"{}\"", lua_quoted(name)))
+            .collect();
+
+        if !others.is_empty() {
+            conflicts.push(format!(
+                "-- Provides virtual '{}'\nconflict({})\n",
+                comment(virtual_name, "-- "),
+                others.join(", ")
+            ));
+        }
+    }
+
+    conflicts.concat()
+}
+
+/// The delimiter argument of Lmod path functions selecting `separator`, if any
+fn delimiter(separator: Option<&String>) -> String {
+    separator
+        .map_or_else(String::new, |sep| format!(", \"{}\"", lua_quoted(sep)))
+}
+
+/// The Lua expression for `val`, joined onto the `root` local of the
+/// modulefile unless it is absolute
+fn path_value(val: &str) -> String {
+    if Path::new(val).is_absolute() {
+        format!("\"{}\"", lua_quoted(val))
+    } else {
+        format!("pathJoin(root, \"{}\")", lua_quoted(val))
+    }
+}
+
+/// The Lua statement applying `value` to `key`, with relative paths resolved
+/// against the `root` local of the modulefile
+fn environment_command(key: &str, value: &Environment) -> String {
+    let key = lua_quoted(key);
+
+    match value {
+        Environment::Set(val) => {
+            format!("setenv(\"{key}\", {})\n", path_value(val))
+        }
+        Environment::SetExact(val) => {
+            format!("setenv(\"{key}\", \"{}\")\n", lua_quoted(val))
+        }
+        Environment::Append(val, sep) => format!(
+            "append_path(\"{key}\", {}{})\n",
+            path_value(val),
+            delimiter(sep.as_ref())
+        ),
+        Environment::Prepend(val, sep) => format!(
+            "prepend_path(\"{key}\", {}{})\n",
+            path_value(val),
+            delimiter(sep.as_ref())
+        ),
+        Environment::RemovePath(val, sep) => format!(
+            "remove_path(\"{key}\", {}{})\n",
+            path_value(val),
+            delimiter(sep.as_ref())
+        ),
+        Environment::Unset => format!("unsetenv(\"{key}\")\n"),
+        Environment::SetAlias(body) => {
+            format!("set_alias(\"{key}\", \"{}\")\n", lua_quoted(body))
+        }
+        // csh has no shell functions, so its body is left empty
+        Environment::SetFunction(body) => format!(
+            "set_shell_function(\"{key}\", \"{}\", \"\")\n",
+            lua_quoted(body)
+        ),
+        Environment::SourceSh(script) => {
+            format!("source_sh(\"{key}\", {})\n", path_value(script))
+        }
+    }
+}
+
+/// The built-in template of Lua modulefiles. See [`generate`] for the
+/// placeholders available.
+pub const TEMPLATE: &str = r#"-- -*- lua -*-
+-- MODULEFILE GENERATED BY SCCMOD
+-- https://github.com/Pencilcaseman/sccmod
+
+-- Metadata
+@metadata@
+help([[
+@help@]])
+
+whatis("@description@")
+
+-- Conflict with other modules of the same class
+@conflict@
+-- Load submodules
+@dependencies@
+-- Make modules built against this one available
+@hierarchy@
+local root = "@root@"
+
+-- Set environment variables
+@environment@"#;
+
+/// Generate a Lua modulefile for Lmod, loading `flavour` of `module`, with
+/// any environment detected in `records` of the flavour. The help text lists
+/// the `installed` flavours.
+///
+/// Flavours are expressed through the module hierarchy: the modulefile is
+/// placed below the level of the modules it was built against, and modules
+/// which flavour others add their own level to `MODULEPATH`.
+///
+/// The modulefile is rendered from the module's template, or [`TEMPLATE`],
+/// with the placeholders `@name@`, `@version@`, `@class@`, `@metadata@`,
+/// `@help@`, `@description@`, `@conflict@`, `@dependencies@`, `@hierarchy@`,
+/// `@root@`, `@revision@` and `@environment@`. `@name@`, `@version@`,
+/// `@class@`, `@description@` and `@root@` are escaped for a double-quoted
+/// string, and `@help@` for a long string.
+///
+/// # Errors
+/// Errors if a versioned dependency cannot be resolved or the template cannot
+/// be read.
+pub fn generate(
+    module: &Module,
+    flavour: &(Vec<Module>, usize),
+    modules: &[Module],
+    records: &[InstallRecord],
+    installed: &[String],
+    config: &config::Config,
+) -> Result<String, String> {
+    let module_metadata = metadata_comments(module, "-- ");
+
+    let module_help = lua_long(&help(module, installed));
+
+    let no_description_provided = "No description provided".to_string();
+    let module_description = lua_quoted(
+        module.metadata.get("description").unwrap_or(&no_description_provided),
+    );
+
+    let conflicts = conflicts(module, modules, config);
+
+    let dependent_modules = module
+        .dependencies
+        .iter()
+        .filter_map(|dep| match dep {
+            Dependency::Depends(name) => Some(name),
+            _ => None,
+        })
+        .map(|name| {
+            let name = if name.contains('@') {
+                flavours::resolve_dependency(modules, name)?.mod_name()
+            } else {
+                name.to_owned()
+            };
+
+            Ok(format!("depends_on(\"{}\")\n", lua_quoted(&name)))
+        })
+        .collect::<Result<String, String>>()?;
+
+    let module = database::at_latest_revision(records, module, flavour);
+    let (_, _, root_dir, _) = module.parse(&(&flavour.0, flavour.1));
+
+    let hierarchy = if opens_level(&module, modules) {
+        let mut members = members(flavour);
+        members.push(&module);
+
+        format!(
+            "prepend_path(\"MODULEPATH\", \"{}\")\n",
+            lua_quoted(&format!(
+                "{}{PATH_SEP}{}",
+                config.modulefile_root,
+                level(&members).to_string_lossy()
+            ))
+        )
+    } else {
+        String::new()
+    };
+
+    let environment_variables: String = environment::resolve(
+        &module,
+        records,
+        Some(&flavours::gen_name(flavour)),
+    )
+    .iter()
+    .map(|(key, value)| environment_command(key, value))
+    .collect();
+
+    let template = template::load(&module, config)?;
+    let revision = module.revision.to_string();
+
+    Ok(template::render(
+        template.as_deref().unwrap_or(TEMPLATE),
+        &[
+            ("name", &lua_quoted(&module.name)),
+            ("version", &lua_quoted(&module.version)),
+            ("class", &lua_quoted(&module.class)),
+            ("metadata", &module_metadata),
+            ("help", &module_help),
+            ("description", &module_description),
+            ("conflict", &conflicts),
+            ("dependencies", &dependent_modules),
+            ("hierarchy", &hierarchy),
+            ("root", &lua_quoted(&root_dir)),
+            ("revision", &revision),
+            ("environment", &environment_variables),
+        ],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_paths_are_not_joined() {
+        let absolute = Environment::SourceSh("/etc/profile.d/tool.sh".into());
+        let relative = Environment::Prepend("bin".into(), None);
+
+        assert_eq!(
+            environment_command("bash", &absolute),
+            "source_sh(\"bash\", \"/etc/profile.d/tool.sh\")\n"
+        );
+        assert_eq!(
+            environment_command("PATH", &relative),
+            "prepend_path(\"PATH\", pathJoin(root, \"bin\"))\n"
+        );
+    }
+}
@@ -14,6 +14,7 @@ fn cli(config: &config::Config) -> Result<(), String> {
                 name: "info",
                 subcommands: Vec::new(),
                 arguments: Vec::new(),
+                flags: Vec::new(),
                 help: "Print sccmod information",
                 callback: Some(callbacks::info),
             },
@@ -21,87 +22,420 @@ fn cli(config: &config::Config) -> Result<(), String> {
                 name: "list",
                 subcommands: Vec::new(),
                 arguments: Vec::new(),
+                flags: Vec::new(),
                 help: "List all available modules",
                 callback: Some(callbacks::list_callback),
             },
+            download_command(),
+            build_command(),
+            install_command(),
+            flavours_command(),
+            uninstall_command(),
+            clean_command(),
+            du_command(),
+            default_command(),
+            gc_command(),
+            status_command(),
+            modulefile_command(),
+            env_command(),
+            exec_command(),
+        ],
+        arguments: vec![],
+        flags: Vec::new(),
+        help: "Automatically build and install modules for Environment Modules",
+        callback: None,
+    };
+
+    let cmd = command.generate_command();
+    command.consume(config, &cmd.get_matches())
+}
+
+fn download_command() -> cli::Command {
+    cli::Command {
+        name: "download",
+        subcommands: vec![cli::Command {
+            name: "all",
+            subcommands: Vec::new(),
+            arguments: Vec::new(),
+            flags: Vec::new(),
+            help: "Download all available modules",
+            callback: Some(callbacks::download_all),
+        }],
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Download the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::download_module,
+        }],
+        flags: Vec::new(),
+        help: "Download a module",
+        callback: None,
+    }
+}
+
+fn build_command() -> cli::Command {
+    cli::Command {
+        name: "build",
+        subcommands: vec![cli::Command {
+            name: "all",
+            subcommands: Vec::new(),
+            arguments: Vec::new(),
+            flags: Vec::new(),
+            help: "Build all available modules",
+            callback: Some(callbacks::build_all),
+        }],
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Build the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::build_module,
+        }],
+        flags: vec![cli::Flag {
+            name: "flavour",
+            help: "Only build matching flavours, e.g. gcc/13,mpich",
+            takes_value: true,
+        }],
+        help: "Build a module",
+        callback: None,
+    }
+}
+
+fn install_command() -> cli::Command {
+    cli::Command {
+        name: "install",
+        subcommands: vec![cli::Command {
+            name: "all",
+            subcommands: Vec::new(),
+            arguments: Vec::new(),
+            flags: Vec::new(),
+            help: "Install all available modules",
+            callback: Some(callbacks::install_all),
+        }],
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Install the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::install_module,
+        }],
+        flags: vec![
+            cli::Flag {
+                name: "flavour",
+                help: "Only build matching flavours, e.g. gcc/13,mpich",
+                takes_value: true,
+            },
+            cli::Flag {
+                name: "with-deps",
+                help: "Also install missing dependencies, in order",
+                takes_value: false,
+            },
+        ],
+        help: "Install a module",
+        callback: None,
+    }
+}
+
+fn flavours_command() -> cli::Command {
+    cli::Command {
+        name: "flavours",
+        subcommands: Vec::new(),
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Show the flavours of the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::flavours_module,
+        }],
+        flags: vec![
+            cli::Flag {
+                name: "flavour",
+                help: "Mark flavours not matching e.g. gcc/13,mpich",
+                takes_value: true,
+            },
+            cli::Flag {
+                name: "json",
+                help: "Print the flavour matrix as JSON",
+                takes_value: false,
+            },
+        ],
+        help: "Show the flavour matrix of a module",
+        callback: None,
+    }
+}
+
+fn uninstall_command() -> cli::Command {
+    cli::Command {
+        name: "uninstall",
+        subcommands: Vec::new(),
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Uninstall the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::uninstall_module,
+        }],
+        flags: vec![
+            cli::Flag {
+                name: "flavour",
+                help: "Only remove matching flavours, e.g. gcc/13,mpich",
+                takes_value: true,
+            },
+            cli::Flag {
+                name: "force",
+                help: "Uninstall even if other modules depend on it",
+                takes_value: false,
+            },
+            cli::Flag {
+                name: "yes",
+                help: "Remove without asking for confirmation",
+                takes_value: false,
+            },
+        ],
+        help: "Remove installed flavours and modulefiles",
+        callback: None,
+    }
+}
+
+fn clean_command() -> cli::Command {
+    cli::Command {
+        name: "clean",
+        subcommands: vec![cli::Command {
+            name: "all",
+            subcommands: Vec::new(),
+            arguments: Vec::new(),
+            flags: Vec::new(),
+            help: "Clean all available modules",
+            callback: Some(callbacks::clean_all),
+        }],
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Clean the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::clean_module,
+        }],
+        flags: vec![
+            cli::Flag {
+                name: "sources",
+                help: "Also remove the downloaded sources",
+                takes_value: false,
+            },
+            cli::Flag {
+                name: "dry-run",
+                help: "List what would be removed and its size",
+                takes_value: false,
+            },
+        ],
+        help: "Remove build directories",
+        callback: None,
+    }
+}
+
+fn du_command() -> cli::Command {
+    cli::Command {
+        name: "du",
+        subcommands: Vec::new(),
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Show the disk usage of the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::du_module,
+        }],
+        flags: Vec::new(),
+        help: "Show disk usage per module, flavour and phase",
+        callback: Some(callbacks::du_all),
+    }
+}
+
+fn default_command() -> cli::Command {
+    cli::Command {
+        name: "default",
+        subcommands: vec![
             cli::Command {
-                name: "download",
-                subcommands: vec![cli::Command {
-                    name: "all",
-                    subcommands: Vec::new(),
-                    arguments: Vec::new(),
-                    help: "Download all available modules",
-                    callback: Some(callbacks::download_all),
-                }],
+                name: "set",
+                subcommands: Vec::new(),
                 arguments: vec![cli::Arg {
                     name: "module",
-                    help: "Download the specified module",
-                    num_params: NumParams::Any,
-                    callback: callbacks::download_module,
+                    help: "The name and version of the module, or \
+`latest` for the highest version",
+                    num_params: NumParams::Multi(2),
+                    callback: callbacks::default_set,
                 }],
-                help: "Download a module",
+                flags: vec![cli::Flag {
+                    name: "flavour",
+                    help: "The flavour to make the default where \
+flavours share a directory, e.g. gcc/13",
+                    takes_value: true,
+                }],
+                help: "Set the version loaded by the module name alone",
                 callback: None,
             },
             cli::Command {
-                name: "build",
-                subcommands: vec![cli::Command {
-                    name: "all",
-                    subcommands: Vec::new(),
-                    arguments: Vec::new(),
-                    help: "Build all available modules",
-                    callback: Some(callbacks::build_all),
-                }],
+                name: "unset",
+                subcommands: Vec::new(),
                 arguments: vec![cli::Arg {
-                    name: "module",
-                    help: "Build the specified module",
-                    num_params: NumParams::Any,
-                    callback: callbacks::build_module,
+                    name: "name",
+                    help: "The name of the module",
+                    num_params: NumParams::Single,
+                    callback: callbacks::default_unset,
                 }],
-                help: "Build a module",
+                flags: Vec::new(),
+                help: "Remove the default version of a module",
                 callback: None,
             },
+        ],
+        arguments: Vec::new(),
+        flags: Vec::new(),
+        help: "Manage the default versions of modules",
+        callback: None,
+    }
+}
+
+fn gc_command() -> cli::Command {
+    cli::Command {
+        name: "gc",
+        subcommands: Vec::new(),
+        arguments: Vec::new(),
+        flags: vec![
+            cli::Flag {
+                name: "days",
+                help: "Collect builds unmodified for this many days",
+                takes_value: true,
+            },
+            cli::Flag {
+                name: "dry-run",
+                help: "List what would be removed and its size",
+                takes_value: false,
+            },
+            cli::Flag {
+                name: "yes",
+                help: "Remove without asking for confirmation",
+                takes_value: false,
+            },
+        ],
+        help: "Remove orphaned trees, old revisions and stale builds",
+        callback: Some(callbacks::gc),
+    }
+}
+
+fn status_command() -> cli::Command {
+    cli::Command {
+        name: "status",
+        subcommands: Vec::new(),
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Show the status of the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::status_module,
+        }],
+        flags: Vec::new(),
+        help: "Show the download, build and install status of modules",
+        callback: Some(callbacks::status_all),
+    }
+}
+
+fn modulefile_command() -> cli::Command {
+    cli::Command {
+        name: "modulefile",
+        subcommands: vec![
             cli::Command {
-                name: "install",
-                subcommands: vec![cli::Command {
-                    name: "all",
-                    subcommands: Vec::new(),
-                    arguments: Vec::new(),
-                    help: "Install all available modules",
-                    callback: Some(callbacks::install_all),
-                }],
-                arguments: vec![cli::Arg {
-                    name: "module",
-                    help: "Install the specified module",
-                    num_params: NumParams::Any,
-                    callback: callbacks::install_module,
-                }],
-                help: "Install a module",
-                callback: None,
+                name: "all",
+                subcommands: Vec::new(),
+                arguments: Vec::new(),
+                flags: Vec::new(),
+                help: "Write modulefiles for all available modules",
+                callback: Some(callbacks::write_modulefile_all),
             },
             cli::Command {
-                name: "modulefile",
-                subcommands: vec![cli::Command {
-                    name: "all",
-                    subcommands: Vec::new(),
-                    arguments: Vec::new(),
-                    help: "Write modulefiles for all available modules",
-                    callback: Some(callbacks::write_modulefile_all),
-                }],
-                arguments: vec![cli::Arg {
-                    name: "module",
-                    help: "Write a modulefile for the specified module",
-                    num_params: NumParams::Any,
-                    callback: callbacks::write_modulefile,
-                }],
-                help: "Automatically generate modulefiles",
-                callback: None,
+                name: "check",
+                subcommands: Vec::new(),
+                arguments: Vec::new(),
+                flags: Vec::new(),
+                help: "Report modulefiles edited by hand or loading \
+missing install paths",
+                callback: Some(callbacks::check_modulefiles_all),
             },
         ],
-        arguments: vec![],
-        help: "Automatically build and install modules for Environment Modules",
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Write a modulefile for the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::write_modulefile,
+        }],
+        flags: vec![
+            cli::Flag {
+                name: "diff",
+                help: "Show how the modulefiles would change instead \
+of writing them",
+                takes_value: false,
+            },
+            cli::Flag {
+                name: "force",
+                help: "Write modulefiles for flavours which are not \
+installed",
+                takes_value: false,
+            },
+        ],
+        help: "Automatically generate modulefiles",
         callback: None,
-    };
+    }
+}
 
-    let cmd = command.generate_command();
-    command.consume(config, &cmd.get_matches())
+fn env_command() -> cli::Command {
+    cli::Command {
+        name: "env",
+        subcommands: Vec::new(),
+        arguments: vec![cli::Arg {
+            name: "module",
+            help: "Print the environment of the specified module",
+            num_params: NumParams::Any,
+            callback: callbacks::env_module,
+        }],
+        flags: vec![
+            cli::Flag {
+                name: "flavour",
+                help: "Activate a matching flavour, e.g. gcc/13,mpich",
+                takes_value: true,
+            },
+            cli::Flag {
+                name: "shell",
+                help: "Print statements for bash, zsh, fish or csh. \
+Defaults to $SHELL",
+                takes_value: true,
+            },
+            cli::Flag {
+                name: "deactivate",
+                help: "Print statements undoing the activation",
+                takes_value: false,
+            },
+        ],
+        help: "Print shell statements activating a module and its \
+dependencies, e.g. eval \"$(sccmod env hdf5)\"",
+        callback: None,
+    }
+}
+
+fn exec_command() -> cli::Command {
+    cli::Command {
+        name: "exec",
+        subcommands: Vec::new(),
+        arguments: vec![
+            cli::Arg {
+                name: "module",
+                help: "The module to run the command in",
+                num_params: NumParams::Any,
+                callback: callbacks::exec_module,
+            },
+            cli::Arg {
+                name: "command",
+                help: "The command to run, after --",
+                num_params: NumParams::Last,
+                callback: callbacks::exec_without_module,
+            },
+        ],
+        flags: vec![cli::Flag {
+            name: "flavour",
+            help: "Use a matching flavour, e.g. gcc/13,mpich",
+            takes_value: true,
+        }],
+        help: "Run a command in the environment of a module and its \
+dependencies, e.g. sccmod exec hdf5 -- make",
+        callback: None,
+    }
 }
@@ -4,12 +4,13 @@ use pyo3::prelude::*;
 
 use crate::{
     builders::builder_trait::{Builder, BuilderImpl},
-    config,
+    config, database, defaults,
     downloaders::{Downloader, DownloaderImpl},
-    file_manager::{recursive_list_dir, PATH_SEP},
-    flavours, log, modulefile,
+    file_manager::{recursive_list_dir, write_atomic, PATH_SEP},
+    flavours, hash, log, modulefile, package_config,
     python_interop::{extract_object, load_program},
     shell::Shell,
+    validate,
 };
 
 pub fn get_submodule_path(parent: &str, submodule: &str) -> String {
@@ -18,18 +19,111 @@ pub fn get_submodule_path(parent: &str, submodule: &str) -> String {
 
 #[derive(Debug, Clone)]
 pub enum Dependency {
-    Class(String),   // Flavours class
-    Module(String),  // Module name
-    Depends(String), // Dependent module name
-    Deny(String),    // Prevent compiling with this flvaour
+    Class(String),            // Flavours class
+    Module(String),           // Module name
+    Depends(String),          // Dependent module name
+    Deny(String),             // Prevent compiling with this flvaour
+    Require(String),          // Only compile with one of these flavours
+    OnlyWith(String, String), // First flavour requires one of the second
+    Virtual(String),          // Any module providing this virtual
 }
 
+impl Dependency {
+    /// Serialise this dependency with [`hash::canonical`], for
+    /// [`Module::fingerprint`]
+    fn fingerprint(&self) -> String {
+        let (kind, values) = match self {
+            Self::Class(name) => ("class", vec![name.clone()]),
+            Self::Module(name) => ("module", vec![name.clone()]),
+            Self::Depends(name) => ("depends", vec![name.clone()]),
+            Self::Deny(name) => ("deny", vec![name.clone()]),
+            Self::Require(name) => ("require", vec![name.clone()]),
+            Self::OnlyWith(first, second) => {
+                ("only_with", vec![first.clone(), second.clone()])
+            }
+            Self::Virtual(name) => ("virtual", vec![name.clone()]),
+        };
+
+        hash::canonical(&[(kind, hash::canonical_list(Some(&values)))])
+    }
+}
+
+/// An operation on the environment, keyed by the variable (or alias,
+/// function or shell) it applies to. Paths are relative to the install prefix
+/// and path lists use the separator given, or `:` if [`None`].
 #[derive(Debug, Clone)]
 pub enum Environment {
     Set(String),
     SetExact(String),
-    Append(String),
-    Prepend(String),
+    Append(String, Option<String>),
+    Prepend(String, Option<String>),
+    RemovePath(String, Option<String>),
+    Unset,
+    SetAlias(String),    // Shell alias body
+    SetFunction(String), // Shell function body
+    SourceSh(String),    // Script to source with the shell of the key
+}
+
+impl Environment {
+    /// The value or path of the operation, if it takes one
+    #[must_use]
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            Self::Set(val)
+            | Self::SetExact(val)
+            | Self::Append(val, _)
+            | Self::Prepend(val, _)
+            | Self::RemovePath(val, _)
+            | Self::SetAlias(val)
+            | Self::SetFunction(val)
+            | Self::SourceSh(val) => Some(val),
+            Self::Unset => None,
+        }
+    }
+}
+
+/// Parse an `(operation, value[, separator])` sequence produced by the
+/// `sccmod.env` helpers into an [`Environment`] operation on `name`.
+fn parse_environment(
+    name: &str,
+    operation: &[String],
+) -> Result<Environment, String> {
+    let (op, value, separator) = match operation {
+        [op, value] => (op.as_str(), value.clone(), None),
+        [op, value, separator] => {
+            (op.as_str(), value.clone(), Some(separator.clone()))
+        }
+        _ => {
+            return Err(format!(
+                "Environment operation on '{name}' must be (operation, value) \
+or (operation, value, separator)"
+            ))
+        }
+    };
+
+    match (op, separator) {
+        ("append", separator) => Ok(Environment::Append(value, separator)),
+        ("prepend", separator) => Ok(Environment::Prepend(value, separator)),
+        ("removepath", separator) => {
+            Ok(Environment::RemovePath(value, separator))
+        }
+        ("set", None) => Ok(Environment::Set(value)),
+        ("setexact", None) => Ok(Environment::SetExact(value)),
+        ("unset", None) => Ok(Environment::Unset),
+        ("setalias", None) => Ok(Environment::SetAlias(value)),
+        ("setfunction", None) => Ok(Environment::SetFunction(value)),
+        ("sourcesh", None) => Ok(Environment::SourceSh(value)),
+        (
+            "set" | "setexact" | "unset" | "setalias" | "setfunction"
+            | "sourcesh",
+            Some(_),
+        ) => Err(format!(
+            "Environment operation '{op}' on '{name}' does not take a separator"
+        )),
+        (other, _) => {
+            Err(format!("Invalid environment variable operation '{other}'"))
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -46,12 +140,24 @@ pub struct Module {
     /// Module dependencies
     pub dependencies: Vec<Dependency>,
 
+    /// Virtual packages this module provides (e.g. `blas` or `mpi`)
+    pub provides: Vec<String>,
+
     /// Module metadata
     pub metadata: HashMap<String, String>,
 
     /// Environment variables to set/change
     pub environment: Vec<(String, Environment)>,
 
+    /// Detect environment variables from the install prefix after installing
+    pub auto_environment: bool,
+
+    /// Path of a template overriding the configured modulefile template
+    pub modulefile_template: Option<String>,
+
+    /// Whether this is the version loaded when only the name is given
+    pub default: bool,
+
     /// A list of commands to run before building
     pub pre_build: Option<Vec<String>>,
 
@@ -67,8 +173,16 @@ pub struct Module {
     pub source_path: String,
     pub build_path: String,
     pub install_path: String,
+
+    /// The revision to build and install. Either declared by the module or
+    /// selected automatically from the build fingerprint
+    pub revision: usize,
 }
 
+/// File in a module's install directory recording the build fingerprint of
+/// each installed revision
+const REVISIONS_FILE: &str = ".sccmod_revisions.toml";
+
 impl Module {
     /// Parse a flavour into:
     ///  - flavour_str: a postfix to a path pointing to a flavour directory
@@ -80,7 +194,7 @@ impl Module {
         flavour: &(&[Module], usize),
     ) -> (String, String, String, Vec<String>) {
         // Generate extension to build path based on flavour
-        let mut flavour_str = format!("{PATH_SEP}1{PATH_SEP}"); // '/1/' for revision
+        let mut flavour_str = format!("{PATH_SEP}{}{PATH_SEP}", self.revision);
 
         // If no class modules are required, install into `default` flavour
         if flavour.1 == 0 {
@@ -184,7 +298,8 @@ impl Module {
         }
     }
 
-    /// Install the source code for this module based on its [`Builder`].
+    /// Install the source code for this module based on its [`Builder`],
+    /// then write its pkg-config and cmake files if it declares its libraries.
     ///
     /// # Errors
     /// Errors if the installation fails. The [`Result`] output contains a
@@ -230,7 +345,7 @@ impl Module {
                 log::status(&"Building...");
             }
 
-            Ok(())
+            package_config::generate(self, std::path::Path::new(&install_path))
         } else {
             log::warn(&format!(
                 "Module '{}' does not have a Builder",
@@ -250,6 +365,8 @@ impl Module {
         config: &config::Config,
     ) -> Result<Self, String> {
         Python::with_gil(|_| {
+            validate_definition(object)?;
+
             let metadata: HashMap<String, String> =
                 extract_object(object, "metadata")?
                     .call0()
@@ -276,27 +393,39 @@ impl Module {
                 .ok_or("Metadata does not contain key 'class'")?
                 .to_owned();
 
-            let downloader: Result<Option<Downloader>, String> =
-                match object.getattr("download") {
-                    Ok(download) => Ok(Some(Downloader::from_py(
-                        &download.call0().map_err(|err| {
-                            format!(
+            for (key, value) in
+                [("name", &name), ("version", &version), ("class", &class)]
+            {
+                if value.trim().is_empty() {
+                    return Err(format!(
+                        "Metadata key '{key}' must not be empty"
+                    ));
+                }
+            }
+
+            let downloader = match object.getattr("download") {
+                Ok(download) => {
+                    let download = download.call0().map_err(|err| {
+                        format!(
                             "Failed to call `download` in module class: {err}"
                         )
-                        })?,
-                    )?)),
-                    Err(_) => Ok(None),
-                };
-            let downloader = downloader?;
+                    })?;
+
+                    if download.is_none() {
+                        None
+                    } else {
+                        Some(Downloader::from_py(&download)?)
+                    }
+                }
+                Err(_) => None,
+            };
 
             let dependencies: Vec<&PyAny> = extract_object(
                 object,
                 "dependencies",
             )?
             .call0()
-            .map_err(|err| {
-                format!("Failed to call `build_requirements`: {err}")
-            })?
+            .map_err(|err| format!("Failed to call `dependencies`: {err}"))?
             .extract()
             .map_err(|err| {
                 format!("Failed to convert `dependencies()` to Rust Vec: {err}")
@@ -321,6 +450,27 @@ impl Module {
                             Err(e) => Err(format!("Could not convert .name attribute to Rust String: {e}"))
                         }
                     },
+                    "<class 'sccmod.module.Require'>" => {
+                        match dep.getattr("name").map_err(|err| format!("Dependency is a Require instance, but does not contain a .name attribute: {err}"))?.extract::<String>() {
+                            Ok(name) => {
+                                Ok(Dependency::Require(name))
+                            },
+                            Err(e) => Err(format!("Could not convert .name attribute to Rust String: {e}"))
+                        }
+                    },
+                    "<class 'sccmod.module.OnlyWith'>" => {
+                        let name = dep.getattr("name").map_err(|err| format!("Dependency is an OnlyWith instance, but does not contain a .name attribute: {err}"))?.extract::<String>().map_err(|e| format!("Could not convert .name attribute to Rust String: {e}"))?;
+                        let partners = dep.getattr("partners").map_err(|err| format!("Dependency is an OnlyWith instance, but does not contain a .partners attribute: {err}"))?.extract::<String>().map_err(|e| format!("Could not convert .partners attribute to Rust String: {e}"))?;
+                        Ok(Dependency::OnlyWith(name, partners))
+                    },
+                    "<class 'sccmod.module.Virtual'>" => {
+                        match dep.getattr("name").map_err(|err| format!("Dependency is a Virtual instance, but does not contain a .name attribute: {err}"))?.extract::<String>() {
+                            Ok(name) => {
+                                Ok(Dependency::Virtual(name))
+                            },
+                            Err(e) => Err(format!("Could not convert .name attribute to Rust String: {e}"))
+                        }
+                    },
                     "<class 'sccmod.module.Depends'>" => {
                         match dep.getattr("name").map_err(|err| format!("Dependency is a Depends instance, but does not contain a .name attribute: {err}"))?.extract::<String>() {
                             Ok(name) => {
@@ -333,7 +483,18 @@ impl Module {
                 }
             }).collect::<Result<Vec<Dependency>, String>>()?;
 
-            let environment: Vec<(String, (String, String))> = extract_object(
+            let provides: Vec<String> = match extract_object(object, "provides") {
+                Ok(obj) => obj
+                    .call0()
+                    .map_err(|err| format!("Failed to call `provides`: {err}"))?
+                    .extract()
+                    .map_err(|err| {
+                        format!("Failed to convert `provides()` to Rust Vec<String>: {err}")
+                    })?,
+                Err(_) => Vec::new(),
+            };
+
+            let environment: Vec<(String, Vec<String>)> = extract_object(
                 object,
                 "environment",
             )?
@@ -341,60 +502,83 @@ impl Module {
             .map_err(|err| format!("Failed to call '.environment()': {err}"))?
             .extract()
             .map_err(|err| {
-                format!("Failed to convert output of `.environment()` to Rust Vec<(String, (String, String))>: {err}")
+                format!("Failed to convert output of `.environment()` to Rust Vec<(String, Vec<String>)>: {err}")
             })?;
 
-            // Convert (String, String) to Environment(String)
+            // Convert (operation, value[, separator]) to Environment
             let environment = environment
                 .into_iter()
-                .map(|(name, (op, value))| match op.as_ref() {
-                    "set" => Ok((name, Environment::Set(value))),
-                    "setexact" => Ok((name, Environment::SetExact(value))),
-                    "append" => Ok((name, Environment::Append(value))),
-                    "prepend" => Ok((name, Environment::Prepend(value))),
-                    other => Err(format!(
-                        "Invalid environment variable operation '{other}'"
-                    )),
+                .map(|(name, op)| {
+                    let op = parse_environment(&name, &op)?;
+                    Ok((name, op))
                 })
                 .collect::<Result<Vec<(String, Environment)>, String>>()?;
 
-            let builder: Result<Option<Builder>, String> = match object
-                .getattr("build")
-            {
-                Ok(download) => Ok(Some(Builder::from_py(
-                    &download.call0().map_err(|err| {
+            // Modules without an explicit setting follow the configuration
+            let auto_environment = object
+                .getattr("auto_environment")
+                .ok()
+                .and_then(|value| value.extract::<Option<bool>>().ok())
+                .flatten()
+                .unwrap_or(config.auto_environment);
+
+            let modulefile_template: Option<String> = object
+                .getattr("modulefile_template")
+                .ok()
+                .map(|value| value.extract())
+                .transpose()
+                .map_err(|err| {
+                    format!(
+                        "`modulefile_template` must be a path or None: {err}"
+                    )
+                })?
+                .flatten();
+
+            let default = object
+                .getattr("default")
+                .ok()
+                .map(|value| value.extract::<Option<bool>>())
+                .transpose()
+                .map_err(|err| format!("`default` must be a boolean: {err}"))?
+                .flatten()
+                .unwrap_or(false);
+
+            let builder = match object.getattr("build") {
+                Ok(build) => {
+                    let build = build.call0().map_err(|err| {
                         format!("Failed to call `build` in module class: {err}")
-                    })?,
-                )?)),
-                Err(_) => Ok(None),
+                    })?;
+
+                    if build.is_none() {
+                        None
+                    } else {
+                        Some(Builder::from_py(&build)?)
+                    }
+                }
+                Err(_) => None,
             };
-            let builder = builder?;
 
             let pre_build: Option<Vec<String>> = match extract_object(object, "pre_build") {
-                Ok(obj) => Some(
-                    obj.call0()
+                Ok(obj) => obj.call0()
                         .map_err(|err| {
                             format!("Failed to call 'pre_build()` in module class: {err}")
                         })?
                         .extract()
                         .map_err(|err| {
-                            format!("Failed to convert object to Rust Vec<String>: {err}")
+                            format!("Failed to convert object to Rust Option<Vec<String>>: {err}")
                         })?,
-                ),
                 Err(_) => None,
             };
 
             let post_install: Option<Vec<String>> = match extract_object(object, "post_install") {
-                Ok(obj) => Some(
-                    obj.call0()
+                Ok(obj) => obj.call0()
                         .map_err(|err| {
                             format!("Failed to call 'post_install()` in module class: {err}")
                         })?
                         .extract()
                         .map_err(|err| {
-                            format!("Failed to convert object to Rust Vec<String>: {err}")
+                            format!("Failed to convert object to Rust Option<Vec<String>>: {err}")
                         })?,
-                ),
                 Err(_) => None,
             };
 
@@ -410,12 +594,26 @@ impl Module {
                 PATH_SEP, config.install_root, class, name, version
             );
 
-            Ok(Self {
+            let declared_revision: Option<usize> = metadata
+                .get("revision")
+                .map(|rev| {
+                    rev.parse()
+                        .ok()
+                        .filter(|rev| *rev > 0)
+                        .ok_or_else(|| format!("Metadata key 'revision' must be a positive integer, not '{rev}'"))
+                })
+                .transpose()?;
+
+            let mut module = Self {
                 name,
                 version,
                 class,
                 dependencies,
+                provides,
                 environment,
+                auto_environment,
+                modulefile_template,
+                default,
                 metadata,
                 pre_build,
                 post_install,
@@ -424,26 +622,276 @@ impl Module {
                 source_path,
                 build_path,
                 install_path,
-            })
+                revision: 1,
+            };
+
+            module.revision = declared_revision
+                .unwrap_or_else(|| module.automatic_revision());
+
+            Ok(module)
         })
     }
 
-    pub fn modulefile(&self) -> Result<(), String> {
+    /// A hash of everything which affects how this module is built. A change
+    /// in the fingerprint results in a new revision being installed.
+    ///
+    /// The hash is taken over an explicit serialisation of the relevant
+    /// settings, so refactoring the types holding them does not change it.
+    #[must_use]
+    pub fn fingerprint(&self) -> String {
+        let dependencies: Vec<String> =
+            self.dependencies.iter().map(Dependency::fingerprint).collect();
+
+        hash::hex_digest(&hash::canonical(&[
+            ("version", self.version.clone()),
+            ("dependencies", hash::canonical_list(Some(&dependencies))),
+            (
+                "downloader",
+                hash::canonical_option(
+                    self.downloader
+                        .as_ref()
+                        .map(DownloaderImpl::fingerprint)
+                        .as_ref(),
+                ),
+            ),
+            (
+                "builder",
+                hash::canonical_option(
+                    self.builder
+                        .as_ref()
+                        .map(BuilderImpl::fingerprint)
+                        .as_ref(),
+                ),
+            ),
+            ("pre_build", hash::canonical_list(self.pre_build.as_ref())),
+            ("post_install", hash::canonical_list(self.post_install.as_ref())),
+        ]))
+    }
+
+    /// List the installed revisions of this module and the fingerprint each
+    /// was built with, in ascending order. Revisions installed before
+    /// fingerprints were recorded have no fingerprint.
+    #[must_use]
+    pub fn installed_revisions(&self) -> Vec<(usize, Option<String>)> {
+        let recorded: toml::Table = std::fs::read_to_string(format!(
+            "{}{PATH_SEP}{REVISIONS_FILE}",
+            self.install_path
+        ))
+        .ok()
+        .and_then(|content| content.parse().ok())
+        .unwrap_or_default();
+
+        let mut revisions: Vec<(usize, Option<String>)> =
+            std::fs::read_dir(&self.install_path)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .filter(|entry| entry.path().is_dir())
+                        .filter_map(|entry| {
+                            entry.file_name().to_str()?.parse::<usize>().ok()
+                        })
+                        .map(|rev| {
+                            let fingerprint = recorded
+                                .get(&rev.to_string())
+                                .and_then(|fp| fp.as_str())
+                                .map(ToString::to_string);
+                            (rev, fingerprint)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+        revisions.sort_unstable();
+        revisions
+    }
+
+    /// The newest installed revision, or [`Module::revision`] if nothing has
+    /// been installed yet
+    #[must_use]
+    pub fn latest_revision(&self) -> usize {
+        self.installed_revisions().last().map_or(self.revision, |(rev, _)| *rev)
+    }
+
+    /// Reuse the newest installed revision if it was built with the current
+    /// fingerprint (or predates fingerprints), otherwise select a new one.
+    fn automatic_revision(&self) -> usize {
+        let fingerprint = self.fingerprint();
+
+        match self.installed_revisions().last() {
+            Some((rev, None)) => *rev,
+            Some((rev, Some(fp))) if fp == &fingerprint => *rev,
+            Some((rev, Some(_))) => rev + 1,
+            None => 1,
+        }
+    }
+
+    /// Record the fingerprint of the current revision in the install
+    /// directory.
+    ///
+    /// # Errors
+    /// Errors if the revisions file cannot be written.
+    pub fn record_revision(&self) -> Result<(), String> {
+        let path = format!("{}{PATH_SEP}{REVISIONS_FILE}", self.install_path);
+
+        let mut recorded: toml::Table = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| content.parse().ok())
+            .unwrap_or_default();
+
+        recorded.insert(
+            self.revision.to_string(),
+            toml::Value::String(self.fingerprint()),
+        );
+
+        std::fs::create_dir_all(&self.install_path)
+            .and_then(|()| std::fs::write(&path, recorded.to_string()))
+            .map_err(|err| format!("Failed to record revision: {err}"))
+    }
+
+    /// The path of the modulefile written for this module
+    #[must_use]
+    pub fn modulefile_path(&self, config: &config::Config) -> String {
+        format!(
+            "{}{PATH_SEP}{}{PATH_SEP}{}{PATH_SEP}{}",
+            config.modulefile_root, self.class, self.name, self.version
+        )
+    }
+
+    /// Write the modulefiles of the installed flavours of this module, warning
+    /// about those which are skipped, resolving dependencies among the
+    /// available `modules`. If `force` is set, modulefiles are written for
+    /// every flavour, installed or not.
+    ///
+    /// Every modulefile is validated before any is written, and each replaces
+    /// the previous one atomically.
+    ///
+    /// # Errors
+    /// Errors if the modulefiles cannot be generated, are invalid or cannot be
+    /// written, or the install records cannot be updated.
+    ///
+    /// # Panics
+    /// Panics if the directory of a modulefile cannot be created.
+    pub fn modulefile(
+        &self,
+        modules: &[Self],
+        config: &config::Config,
+        force: bool,
+    ) -> Result<(), String> {
         // Write modulefile
         log::status(&format!("Writing Modulefile for {}", self.mod_name()));
-        let conf = config::read()?;
-        let dir = format!(
-            "{}{PATH_SEP}{}{PATH_SEP}{}{PATH_SEP}{}",
-            conf.modulefile_root, self.class, self.name, self.version
-        );
-        let dir = std::path::Path::new(&dir);
+        let outputs = modulefile::outputs(self, modules, config, force)?;
 
-        let content = modulefile::generate(&self);
+        if !force {
+            self.warn_uninstalled(modules, config)?;
+        }
 
-        std::fs::create_dir_all(dir.parent().unwrap()).unwrap();
-        std::fs::write(dir, content)
-            .map_err(|err| format!("Failed to write modulefile: {err}"))
+        for output in &outputs {
+            validate::modulefile(&output.content, config.modulefile_format)
+                .map_err(|err| {
+                    format!(
+                        "Generated modulefile {} is invalid, so it was not written: {err}",
+                        output.path
+                    )
+                })?;
+        }
+
+        for output in &outputs {
+            let dir = std::path::Path::new(&output.path);
+
+            std::fs::create_dir_all(dir.parent().unwrap()).unwrap();
+            write_atomic(&dir, &output.content)
+                .map_err(|err| format!("Failed to write modulefile: {err}"))?;
+        }
+
+        // Note the modulefile in the install records of this module
+        let written_at = database::timestamp();
+        for mut record in database::records_for(self, config)? {
+            if let Some(output) =
+                outputs.iter().find(|output| output.covers(&record.flavour))
+            {
+                record.modulefile = Some(output.path.clone());
+                record.modulefile_written_at = Some(written_at);
+                record.write(config)?;
+            }
+        }
+
+        if self.default && !outputs.is_empty() {
+            let selection = flavours::Selection::new(config, None, modules)?;
+            defaults::set(self, modules, &selection, config)?;
+        }
+
+        Ok(())
     }
+
+    /// Warn about the flavours of this module whose modulefiles are skipped
+    /// because they are not installed
+    fn warn_uninstalled(
+        &self,
+        modules: &[Self],
+        config: &config::Config,
+    ) -> Result<(), String> {
+        let records = database::records_for(self, config)?;
+
+        let installed: Vec<String> =
+            modulefile::installed_flavours(self, modules, &records)?
+                .iter()
+                .map(flavours::gen_name)
+                .collect();
+
+        let skipped: Vec<String> = flavours::generate_with(self, modules)?
+            .iter()
+            .map(flavours::gen_name)
+            .filter(|name| !installed.contains(name))
+            .collect();
+
+        if installed.is_empty() && !skipped.is_empty() {
+            log::warn(&format!(
+                "'{}' is not installed, so no modulefile was written. Use --force to write it anyway",
+                self.identifier()
+            ));
+        } else if !skipped.is_empty() {
+            log::warn(&format!(
+                "Skipped flavours of '{}' which are not installed: {}. Use --force to include them",
+                self.identifier(),
+                skipped.join(" ")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that a Python object can be used as a module definition.
+///
+/// Instances of the `sccmod.module.Module` base class always provide the
+/// required methods. Other objects must define them explicitly.
+///
+/// # Errors
+/// Errors if a required method is missing, with a message pointing towards the
+/// base class.
+fn validate_definition(object: &Bound<PyAny>) -> Result<(), String> {
+    let py = object.py();
+
+    let is_module = py
+        .import_bound("sccmod.module")
+        .and_then(|module| module.getattr("Module"))
+        .and_then(|base| object.is_instance(&base))
+        .unwrap_or(false);
+
+    if is_module {
+        return Ok(());
+    }
+
+    for method in ["metadata", "dependencies", "environment"] {
+        if !object.hasattr(method).unwrap_or(false) {
+            return Err(format!(
+                "Module definition '{object}' does not define `{method}`. \
+Consider deriving from `sccmod.module.Module`, which provides defaults"
+            ));
+        }
+    }
+
+    Ok(())
 }
 
 /// List all available modules
@@ -509,18 +957,33 @@ pub fn download(module: &Module) -> Result<(), String> {
     module.download()
 }
 
-/// Download and build a module.
-///
-/// # Errors
-/// Errors if [`Module.download`] fails or [`Module.build`] fails.
-pub fn build(module: &Module) -> Result<(), String> {
-    download(module)?;
+/// Generate the flavours of `module` permitted by `selection`, warning if
+/// there are none.
+fn selected_flavours(
+    module: &Module,
+    modules: &[Module],
+    selection: &flavours::Selection,
+) -> Result<Vec<(Vec<Module>, usize)>, String> {
+    let flavs = selection.filter(flavours::generate(module, modules)?);
+
+    if flavs.is_empty() {
+        log::warn(&format!(
+            "No flavours of '{}' match the flavour selection",
+            module.identifier()
+        ));
+    }
 
-    log::status(&format!("Building '{}-{}'", module.name, module.version));
+    Ok(flavs)
+}
 
-    let flavs = flavours::generate(module)?;
+/// Build `flavs` of a module, which must already be downloaded
+fn build_flavours(
+    module: &Module,
+    flavs: &[(Vec<Module>, usize)],
+) -> Result<(), String> {
+    log::status(&format!("Building '{}-{}'", module.name, module.version));
 
-    for flav in &flavs {
+    for flav in flavs {
         log::info(&format!("Building flavour {}", flavours::gen_name(flav)));
         module.build((&flav.0, flav.1))?;
     }
@@ -528,25 +991,209 @@ pub fn build(module: &Module) -> Result<(), String> {
     Ok(())
 }
 
-/// Download, build and install a module.
+/// Download and build the selected flavours of a module, selecting
+/// dependencies from the available `modules`.
+///
+/// # Errors
+/// Errors if [`Module.download`] fails or [`Module.build`] fails.
+pub fn build(
+    module: &Module,
+    modules: &[Module],
+    selection: &flavours::Selection,
+) -> Result<(), String> {
+    download(module)?;
+    build_flavours(module, &selected_flavours(module, modules, selection)?)
+}
+
+/// The flavours among `all` which are installed according to `records`, but
+/// only at a revision older than that of `module`, and are not in `selected`.
+///
+/// A Tcl modulefile loads every flavour from a single revision, so these must
+/// be rebuilt alongside `selected` when a new revision is installed.
+#[must_use]
+pub fn outdated_flavours(
+    module: &Module,
+    records: &[database::InstallRecord],
+    all: Vec<(Vec<Module>, usize)>,
+    selected: &[(Vec<Module>, usize)],
+) -> Vec<(Vec<Module>, usize)> {
+    let selected: Vec<String> =
+        selected.iter().map(flavours::gen_name).collect();
+
+    all.into_iter()
+        .filter(|flav| {
+            let name = flavours::gen_name(flav);
+
+            !selected.contains(&name)
+                && database::latest_revision(records, module, &name)
+                    .is_some_and(|revision| revision < module.revision)
+        })
+        .collect()
+}
+
+/// Download, build and install the selected flavours of a module, selecting
+/// dependencies from the available `modules`.
+///
+/// With Tcl modulefiles, flavours installed at an older revision are rebuilt
+/// too, as the modulefile loads every flavour from the newest revision.
 ///
 /// # Errors
 /// Errors if [`Module.download`], [`Module.build`] or [`Module.install`] fails.
-pub fn install(module: &Module) -> Result<(), String> {
-    build(module)?;
+pub fn install(
+    module: &Module,
+    modules: &[Module],
+    selection: &flavours::Selection,
+    config: &config::Config,
+) -> Result<(), String> {
+    let mut flavs = selected_flavours(module, modules, selection)?;
+
+    if config.modulefile_format == config::ModulefileFormat::Tcl
+        && module.builder.is_some()
+    {
+        let records = database::records_for(module, config)?;
+        let outdated = outdated_flavours(
+            module,
+            &records,
+            flavours::generate_with(module, modules)?,
+            &flavs,
+        );
 
-    log::status(&format!("Installing '{}-{}'", module.name, module.version));
+        for flav in &outdated {
+            log::info(&format!(
+                "Rebuilding flavour {} at revision {}, as the modulefile loads every flavour from one revision",
+                flavours::gen_name(flav),
+                module.revision
+            ));
+        }
+
+        flavs.extend(outdated);
+    }
+
+    download(module)?;
+    build_flavours(module, &flavs)?;
 
-    let flavs = flavours::generate(module)?;
+    log::status(&format!(
+        "Installing '{}-{}' (revision {})",
+        module.name, module.version, module.revision
+    ));
 
     for flav in &flavs {
         log::info(&format!("Installing flavour {}", flavours::gen_name(flav)));
         module.install((&flav.0, flav.1))?;
+
+        if module.builder.is_some() {
+            database::InstallRecord::new(module, flav).write(config)?;
+        }
+    }
+
+    if module.builder.is_some() && !flavs.is_empty() {
+        module.record_revision()?;
     }
 
-    module.modulefile()
+    module.modulefile(modules, config, false)
+}
+
+/// Write the modulefiles of a module. See [`Module::modulefile`].
+///
+/// # Errors
+/// Errors if [`Module::modulefile`] fails.
+pub fn modulefile(
+    module: &Module,
+    modules: &[Module],
+    config: &config::Config,
+    force: bool,
+) -> Result<(), String> {
+    module.modulefile(modules, config, force)
 }
 
-pub fn modulefile(module: &Module) -> Result<(), String> {
-    module.modulefile()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{plain, testing};
+
+    #[test]
+    fn absolute_and_relative_source_sh() {
+        let op = |value: &str| {
+            parse_environment("bash", &["sourcesh".into(), value.into()])
+                .unwrap()
+        };
+        let (absolute, relative) =
+            (op("/etc/profile.d/tool.sh"), op("share/env.sh"));
+
+        assert!(
+            matches!(&absolute, Environment::SourceSh(script) if script == "/etc/profile.d/tool.sh")
+        );
+
+        assert_eq!(
+            plain::environment_command("bash", &absolute, "/opt/tool/1"),
+            "source-sh \"bash\" \"/etc/profile.d/tool.sh\"\n"
+        );
+        assert_eq!(
+            plain::environment_command("bash", &relative, "/opt/tool/1"),
+            "source-sh \"bash\" \"/opt/tool/1/share/env.sh\"\n"
+        );
+    }
+
+    #[test]
+    fn subset_installed_after_fingerprint_change() {
+        let root = testing::temp_dir("subset-revision");
+        let root = root.to_str().unwrap();
+
+        let modules = vec![
+            testing::module(root, "compiler/gcc/12", Vec::new()),
+            testing::module(root, "compiler/gcc/13", Vec::new()),
+        ];
+        let app = testing::module(
+            root,
+            "apps/app/1.0",
+            vec![Dependency::Class("compiler".to_string())],
+        );
+
+        let all = flavours::generate_with(&app, &modules).unwrap();
+        let (gcc12, gcc13) = (&all[0], &all[1]);
+
+        // Both flavours at revision 1, then only gcc 13 at revision 2
+        let records = vec![
+            testing::record(&app, gcc12, 1),
+            testing::record(&app, gcc13, 1),
+            testing::record(&app, gcc13, 2),
+        ];
+
+        let name = |flav| flavours::gen_name(flav);
+        assert_eq!(
+            database::latest_revision(&records, &app, &name(gcc12)),
+            Some(1)
+        );
+        assert_eq!(
+            database::latest_revision(&records, &app, &name(gcc13)),
+            Some(2)
+        );
+
+        let installed: Vec<String> =
+            modulefile::installed_flavours(&app, &modules, &records)
+                .unwrap()
+                .iter()
+                .map(flavours::gen_name)
+                .collect();
+        assert_eq!(installed, vec![name(gcc12), name(gcc13)]);
+
+        let (_, _, path, _) =
+            database::at_latest_revision(&records, &app, gcc12)
+                .parse(&(&gcc12.0, gcc12.1));
+        assert!(path.ends_with("/1/gcc-12"));
+
+        // Installing gcc 13 at revision 2 leaves gcc 12 behind
+        let app = Module { revision: 2, ..app };
+        let outdated = outdated_flavours(
+            &app,
+            &records,
+            all.clone(),
+            std::slice::from_ref(gcc13),
+        );
+        let outdated: Vec<String> =
+            outdated.iter().map(flavours::gen_name).collect();
+        assert_eq!(outdated, vec![name(gcc12)]);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
 }
@@ -1,82 +1,230 @@
+use std::path::Path;
+
 use crate::{
-    config,
+    config::{self, ModulefileFormat},
+    database::{self, InstallRecord},
+    environment,
+    escape::{comment, tcl_quoted, tcl_word},
+    flavours, hash, lmod,
     module::{Dependency, Environment, Module},
+    plain, template,
 };
 
+/// A generated modulefile and the path it should be written to
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub path: String,
+
+    /// The flavour the modulefile loads, or [`None`] if it covers every
+    /// flavour of the module
+    pub flavour: Option<String>,
+
+    pub content: String,
+}
+
+impl Output {
+    /// Returns `true` if this modulefile loads the flavour named `flavour`
+    #[must_use]
+    pub fn covers(&self, flavour: &str) -> bool {
+        self.flavour.as_ref().is_none_or(|name| name == flavour)
+    }
+}
+
+/// Start of the header line recording the hash of a generated modulefile
+const HASH_LINE: &str = "sccmod-hash: ";
+
+/// Embed the hash of `content` on the line after its first, so that changes
+/// made after generation can be detected. Lua modulefiles use `--` comments,
+/// Tcl ones `#`.
+///
+/// ```
+/// use sccmod::{config::ModulefileFormat, modulefile::{seal, unseal}};
+///
+/// let sealed = seal("#%Module\nsetenv A 1\n", ModulefileFormat::Tcl);
+/// assert!(sealed.starts_with("#%Module\n# sccmod-hash: "));
+///
+/// let (hash, content) = unseal(&sealed);
+/// assert_eq!(content, "#%Module\nsetenv A 1\n");
+/// assert_eq!(hash, Some(sccmod::hash::hex_digest(&content)));
+/// ```
 #[must_use]
-pub fn generate(module: &Module) -> String {
-    // Generate a modulefile with support for flavours
-    // The modulefile has the following format:
+pub fn seal(content: &str, format: ModulefileFormat) -> String {
+    let comment = match format {
+        ModulefileFormat::Lua => "--",
+        ModulefileFormat::Tcl | ModulefileFormat::Plain => "#",
+    };
 
-    let config = config::read().unwrap();
+    let (first, rest) = content.split_once('\n').unwrap_or((content, ""));
 
-    let module_class = &module.class;
+    format!(
+        "{first}\n{comment} {HASH_LINE}{}\n{rest}",
+        hash::hex_digest(content)
+    )
+}
 
-    let module_conflict =
-        if config.class_no_conflict.contains(&module_class.to_string()) {
-            String::new()
-        } else {
-            format!("::flavours::conflict -class {module_class}\n")
-        };
+/// Split a modulefile into the hash embedded by [`seal`], if any, and the
+/// content without it
+#[must_use]
+pub fn unseal(content: &str) -> (Option<String>, String) {
+    let mut hash = None;
+    let mut lines = Vec::new();
 
-    let mut module_metadata_str = String::new();
-    for (key, value) in &module.metadata {
-        module_metadata_str.push_str(&format!("# {key}: {value}\n"));
-    }
+    for line in content.split_inclusive('\n') {
+        let embedded = line
+            .strip_prefix("# ")
+            .or_else(|| line.strip_prefix("-- "))
+            .and_then(|line| line.strip_prefix(HASH_LINE));
 
-    let mut module_metadata_str_no_hashes = String::new();
-    for (key, value) in &module.metadata {
-        module_metadata_str_no_hashes.push_str(&format!("{key}: {value}\n"));
+        match embedded {
+            Some(embedded) if hash.is_none() => {
+                hash = Some(embedded.trim().to_string());
+            }
+            _ => lines.push(line),
+        }
     }
 
-    let no_description_provided = "No description provided".to_string();
-    let module_description =
-        module.metadata.get("description").unwrap_or(&no_description_provided);
+    (hash, lines.concat())
+}
+
+/// The flavours of `module` installed at any revision according to `records`.
+/// Every flavour of a module without a builder counts as installed.
+///
+/// # Errors
+/// Errors if the flavours of `module` cannot be generated.
+pub fn installed_flavours(
+    module: &Module,
+    modules: &[Module],
+    records: &[InstallRecord],
+) -> Result<Vec<(Vec<Module>, usize)>, String> {
+    Ok(flavours::generate_with(module, modules)?
+        .into_iter()
+        .filter(|flav| {
+            module.builder.is_none()
+                || database::latest_revision(
+                    records,
+                    module,
+                    &flavours::gen_name(flav),
+                )
+                .is_some()
+        })
+        .collect())
+}
+
+/// The metadata of `module` as comments starting with `prefix`, sorted so
+/// generated modulefiles are deterministic
+#[must_use]
+pub fn metadata_comments(module: &Module, prefix: &str) -> String {
+    let mut metadata: Vec<(&String, &String)> =
+        module.metadata.iter().collect();
+    metadata.sort();
 
-    let dependent_modules = module
-        .dependencies
+    metadata
         .iter()
-        .filter_map(|dep| {
-            if let Dependency::Depends(name) = dep {
-                Some(format!("depends-on {}\n", name))
-            } else {
-                None
-            }
+        .map(|(key, value)| {
+            format!(
+                "{prefix}{}: {}\n",
+                comment(key, prefix),
+                comment(value, prefix)
+            )
         })
-        .fold(String::new(), |mut acc, dep| {
-            acc.push_str(&dep);
-            acc
-        });
+        .collect::<Vec<String>>()
+        .concat()
+}
 
-    let mut class_definitions = String::new();
-    for class in module.dependencies.iter().filter_map(|dep| {
-        if let Dependency::Class(name) = dep {
-            Some(name)
-        } else {
-            None
-        }
-    }) {
-        class_definitions
-            .push_str(&format!("::flavours::prereq -class {class}\n"));
-    }
+/// The help text of a modulefile: the metadata of `module`, followed by the
+/// names of its `installed` flavours
+#[must_use]
+pub fn help(module: &Module, installed: &[String]) -> String {
+    let mut metadata: Vec<(&String, &String)> =
+        module.metadata.iter().collect();
+    metadata.sort();
 
-    let root_dir = &module.install_path;
-
-    let mut environment_variables = String::new();
-    for (key, value) in &module.environment {
-        environment_variables.push_str(&match value {
-            // Environment::Set(val) => format!("setenv \"{key}\" \"{val}\"\n"),
-            Environment::Set(val) => format!("::flavours::modify-path setenv \"{key}\" \"{val}\"\n"),
-            Environment::SetExact(val) => format!(" setenv \"{key}\" \"{val}\"\n"),
-            Environment::Append(val) => format!("::flavours::append-path \"{key}\" \"{val}\"\n"),
-            Environment::Prepend(val) => {
-                format!("::flavours::modify-path prepend-path \"{key}\" \"{val}\"\n")
-            }
-        });
-    }
+    let installed = if installed.is_empty() {
+        "none".to_string()
+    } else {
+        installed.join(" ")
+    };
 
-    format!(
-        r#"#%Module
+    metadata
+        .iter()
+        .map(|(key, value)| format!("{key}: {value}\n"))
+        .chain(std::iter::once(format!("installed flavours: {installed}\n")))
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+/// Generate the modulefiles of the installed flavours of `module` in the
+/// configured format, each with its hash embedded by [`seal`].
+///
+/// Dependencies are resolved among the available `modules`. If `force` is
+/// set, modulefiles are generated for every flavour.
+///
+/// # Errors
+/// Errors if the install records cannot be read, or the modulefiles cannot be
+/// generated.
+pub fn outputs(
+    module: &Module,
+    modules: &[Module],
+    config: &config::Config,
+    force: bool,
+) -> Result<Vec<Output>, String> {
+    let records = database::records_for(module, config)?;
+
+    let installed = installed_flavours(module, modules, &records)?;
+    let names: Vec<String> = installed.iter().map(flavours::gen_name).collect();
+
+    let selected = if force {
+        flavours::generate_with(module, modules)?
+    } else {
+        installed
+    };
+
+    let outputs = match config.modulefile_format {
+        // A single modulefile selects between the flavours when loaded
+        ModulefileFormat::Tcl if selected.is_empty() => Vec::new(),
+        ModulefileFormat::Tcl => vec![Output {
+            path: module.modulefile_path(config),
+            flavour: None,
+            content: generate(module, modules, &records, &names, config)?,
+        }],
+        ModulefileFormat::Lua => selected
+            .iter()
+            .map(|flav| {
+                Ok(Output {
+                    path: lmod::path(module, flav, config),
+                    flavour: Some(flavours::gen_name(flav)),
+                    content: lmod::generate(
+                        module, flav, modules, &records, &names, config,
+                    )?,
+                })
+            })
+            .collect::<Result<Vec<Output>, String>>()?,
+        ModulefileFormat::Plain => selected
+            .iter()
+            .map(|flav| {
+                Ok(Output {
+                    path: plain::path(module, flav, config),
+                    flavour: Some(flavours::gen_name(flav)),
+                    content: plain::generate(
+                        module, flav, modules, &records, &names, config,
+                    )?,
+                })
+            })
+            .collect::<Result<Vec<Output>, String>>()?,
+    };
+
+    Ok(outputs
+        .into_iter()
+        .map(|output| Output {
+            content: seal(&output.content, config.modulefile_format),
+            ..output
+        })
+        .collect())
+}
+
+/// The built-in template of modulefiles using the `flavours` Tcl extension.
+/// See [`generate`] for the placeholders available.
+pub const TEMPLATE: &str = r#"#%Module
 # MODULEFILE GENERATED BY SCCMOD
 # https://github.com/Pencilcaseman/sccmod
 
@@ -84,60 +232,262 @@ package require flavours
 
 # Flavours is a bit of a pain, so we have to redefine it sometimes.
 # Don't ask me why.
-if {{![info exists flavours] || [info procs flavours] eq ""}} {{
-    proc flavours {{comm args}} {{
+if {![info exists flavours] || [info procs flavours] eq ""} {
+    proc flavours {comm args} {
         # "whatis" is fragile: don't do anything!
-        if {{ [module-info mode whatis] }} {{ return }}
-        if {{ [module-info mode help]  }} {{ return }}
+        if { [module-info mode whatis] } { return }
+        if { [module-info mode help]  } { return }
 
         # Switchboard
-        switch $comm {{
+        switch $comm {
             append-path  -
             prepend-path -
-            remove-path  {{ eval ::flavours::modify-path $comm $args }}
-            get          {{ eval ::flavours::get_$args }}
-            default      {{ eval ::flavours::$comm $args }}
-        }}
-    }}
+            remove-path  { eval ::flavours::modify-path $comm $args }
+            get          { eval ::flavours::get_$args }
+            default      { eval ::flavours::$comm $args }
+        }
+    }
 
     # Uncomment this for debug information.
     # puts stderr "Redefined flavours procedure"
-}}
+}
 
 flavours init
 
 # Metadata
-{module_metadata_str}
+@metadata@
 
 # Module help
-proc ModulesHelp {{ }} {{
+proc ModulesHelp { } {
    puts stderr "
-{module_metadata_str_no_hashes}
+@help@
 "
-}}
+}
 
-module-whatis "{module_description}"
+module-whatis "@description@"
 
 # Load submodules
-{dependent_modules}
+@dependencies@
 
 # Module prerequisites
-{class_definitions}
+@classes@
 
 # Conflict with other modules of the same class
-# ::flavours::conflict -class {module_class}
-{module_conflict}
+# ::flavours::conflict -class @class@
+@conflict@
 
 # Evaluate the flavour
-::flavours::root     {root_dir}
-::flavours::revision 1
+::flavours::root     @root@
+::flavours::revision @revision@
 ::flavours::commit
 
 # Set environment variables
-{environment_variables}
+@environment@
 
 # Cleanup and reload conflicting modules
 ::flavours::cleanup
-"#
-    )
+"#;
+
+/// List the names of the modules providing `virtual_name`
+#[must_use]
+pub fn providers<'a>(
+    modules: &'a [Module],
+    virtual_name: &String,
+) -> Vec<&'a str> {
+    let mut providers: Vec<&str> = modules
+        .iter()
+        .filter(|m| m.provides.contains(virtual_name))
+        .map(|m| m.name.as_str())
+        .collect();
+
+    providers.sort_unstable();
+    providers.dedup();
+    providers
+}
+
+/// The `-d` option of Tcl path commands selecting `separator`, if any
+#[must_use]
+pub fn delimiter_option(separator: Option<&String>) -> String {
+    separator
+        .map_or_else(String::new, |sep| format!(" -d \"{}\"", tcl_quoted(sep)))
+}
+
+/// The Tcl command applying `value` to `key`, with relative paths evaluated
+/// against the flavour root by the `flavours` extension. Absolute paths are
+/// applied unchanged.
+fn environment_command(key: &str, value: &Environment) -> String {
+    if value.value().is_some_and(|val| Path::new(val).is_absolute()) {
+        return plain::environment_command(key, value, "");
+    }
+
+    let key = tcl_quoted(key);
+
+    match value {
+        Environment::Set(val) => format!(
+            "::flavours::modify-path setenv \"{key}\" \"{}\"\n",
+            tcl_quoted(val)
+        ),
+        Environment::SetExact(val) => {
+            format!(" setenv \"{key}\" \"{}\"\n", tcl_quoted(val))
+        }
+        Environment::Append(val, sep) => format!(
+            "::flavours::append-path{} \"{key}\" \"{}\"\n",
+            delimiter_option(sep.as_ref()),
+            tcl_quoted(val)
+        ),
+        Environment::Prepend(val, sep) => format!(
+            "::flavours::modify-path prepend-path{} \"{key}\" \"{}\"\n",
+            delimiter_option(sep.as_ref()),
+            tcl_quoted(val)
+        ),
+        Environment::RemovePath(val, sep) => format!(
+            "::flavours::modify-path remove-path{} \"{key}\" \"{}\"\n",
+            delimiter_option(sep.as_ref()),
+            tcl_quoted(val)
+        ),
+        Environment::Unset => format!("unsetenv \"{key}\"\n"),
+        Environment::SetAlias(body) => {
+            format!("set-alias \"{key}\" \"{}\"\n", tcl_quoted(body))
+        }
+        Environment::SetFunction(body) => {
+            format!("set-function \"{key}\" \"{}\"\n", tcl_quoted(body))
+        }
+        Environment::SourceSh(script) => format!(
+            "::flavours::modify-path source-sh \"{key}\" \"{}\"\n",
+            tcl_quoted(script)
+        ),
+    }
+}
+
+/// Generate the content of a modulefile for `module`, from its template or
+/// [`TEMPLATE`].
+///
+/// Dependencies are resolved among the available `modules`, and the paths
+/// detected in its install `records` are included. The help text lists the
+/// `installed` flavours.
+///
+/// The template placeholders are `@name@`, `@version@`, `@class@`,
+/// `@metadata@`, `@help@`, `@description@`, `@dependencies@`, `@classes@`,
+/// `@conflict@`, `@root@`, `@revision@` and `@environment@`. `@help@` and
+/// `@description@` are escaped for a double-quoted string. `@name@`,
+/// `@version@`, `@class@` and `@root@` are escaped for a bare word, which is
+/// also valid inside a double-quoted string.
+///
+/// # Errors
+/// Errors if a versioned dependency cannot be resolved or the template cannot
+/// be read.
+pub fn generate(
+    module: &Module,
+    modules: &[Module],
+    records: &[InstallRecord],
+    installed: &[String],
+    config: &config::Config,
+) -> Result<String, String> {
+    let module_class = &module.class;
+
+    let mut module_conflict =
+        if config.class_no_conflict.contains(&module_class.to_string()) {
+            String::new()
+        } else {
+            format!("::flavours::conflict -class {}\n", tcl_word(module_class))
+        };
+
+    // Only one provider of each virtual may be loaded at once
+    for virtual_name in &module.provides {
+        let others: Vec<String> = providers(modules, virtual_name)
+            .into_iter()
+            .filter(|name| *name != module.name)
+            .map(tcl_word)
+            .collect();
+
+        if !others.is_empty() {
+            module_conflict.push_str(&format!(
+                "# Provides virtual '{}'\nconflict {}\n",
+                comment(virtual_name, "# "),
+                others.join(" ")
+            ));
+        }
+    }
+
+    let module_metadata_str = metadata_comments(module, "# ");
+
+    let module_metadata_str_no_hashes = tcl_quoted(&help(module, installed));
+
+    let no_description_provided = "No description provided".to_string();
+    let module_description = tcl_quoted(
+        module.metadata.get("description").unwrap_or(&no_description_provided),
+    );
+
+    // Dependencies with a version requirement are pinned to the module
+    // selected during flavour generation
+    let mut dependent_modules = String::new();
+    for name in module.dependencies.iter().filter_map(|dep| {
+        if let Dependency::Depends(name) = dep {
+            Some(name)
+        } else {
+            None
+        }
+    }) {
+        let name = if name.contains('@') {
+            flavours::resolve_dependency(modules, name)?.mod_name()
+        } else {
+            name.to_owned()
+        };
+
+        dependent_modules
+            .push_str(&format!("depends-on {}\n", tcl_word(&name)));
+    }
+
+    let mut class_definitions = String::new();
+    for dep in &module.dependencies {
+        match dep {
+            Dependency::Class(class) => class_definitions.push_str(&format!(
+                "::flavours::prereq -class {}\n",
+                tcl_word(class)
+            )),
+            // Any one of the providers satisfies the prerequisite
+            Dependency::Virtual(virtual_name) => {
+                class_definitions.push_str(&format!(
+                    "::flavours::prereq {}\n",
+                    providers(modules, virtual_name)
+                        .into_iter()
+                        .map(tcl_word)
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    let root_dir = tcl_word(&module.install_path);
+    let revision = module.latest_revision();
+
+    // Every flavour shares this modulefile, so it includes the paths detected
+    // in any of them
+    let environment_variables = environment::resolve(module, records, None)
+        .iter()
+        .map(|(key, value)| environment_command(key, value))
+        .collect::<Vec<String>>()
+        .concat();
+
+    let template = template::load(module, config)?;
+
+    Ok(template::render(
+        template.as_deref().unwrap_or(TEMPLATE),
+        &[
+            ("name", &tcl_word(&module.name)),
+            ("version", &tcl_word(&module.version)),
+            ("class", &tcl_word(module_class)),
+            ("metadata", &module_metadata_str),
+            ("help", &module_metadata_str_no_hashes),
+            ("description", &module_description),
+            ("dependencies", &dependent_modules),
+            ("classes", &class_definitions),
+            ("conflict", &module_conflict),
+            ("root", &root_dir),
+            ("revision", &revision.to_string()),
+            ("environment", &environment_variables),
+        ],
+    ))
 }
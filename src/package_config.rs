@@ -0,0 +1,309 @@
+use std::path::{Path, PathBuf};
+
+use crate::{escape::cmake_quoted, log, module::Module};
+
+/// First line of every file written here, used to tell them apart from files
+/// installed by the build itself
+const HEADER: &str = "GENERATED BY SCCMOD";
+
+/// The words of the `key` metadata of `module`, separated by spaces or commas
+fn declared(module: &Module, key: &str) -> Vec<String> {
+    module.metadata.get(key).map_or_else(Vec::new, |value| {
+        value
+            .split([' ', ','])
+            .filter(|word| !word.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
+/// The libraries `module` declares in the `libs` metadata key, as passed to
+/// `-l`, e.g. `hdf5 hdf5_hl`
+#[must_use]
+pub fn libraries(module: &Module) -> Vec<String> {
+    declared(module, "libs")
+}
+
+/// The include directories `module` declares in the `includes` metadata key,
+/// relative to its install prefix. Defaults to `include`.
+#[must_use]
+pub fn include_dirs(module: &Module) -> Vec<String> {
+    let dirs = declared(module, "includes");
+
+    if dirs.is_empty() {
+        vec!["include".to_string()]
+    } else {
+        dirs
+    }
+}
+
+/// The library directory of `prefix`, relative to it: `lib64` if only it
+/// exists, otherwise `lib`
+fn lib_dir(prefix: &Path) -> &'static str {
+    if prefix.join("lib64").is_dir() && !prefix.join("lib").is_dir() {
+        "lib64"
+    } else {
+        "lib"
+    }
+}
+
+/// Escape `value` for a pkg-config variable or field
+fn pc_escaped(value: &str) -> String {
+    value.replace('$', "$$").replace(' ', "\\ ")
+}
+
+/// Escape `value` for a single-line pkg-config field which is not split into
+/// arguments, such as `Name`
+fn pc_field(value: &str) -> String {
+    value.replace('$', "$$").replace(['\n', '\r'], " ")
+}
+
+/// Generate a pkg-config file for `module` installed in `prefix`
+#[must_use]
+pub fn pkg_config(module: &Module, prefix: &Path) -> String {
+    let description = module
+        .metadata
+        .get("description")
+        .map_or(String::new(), |desc| pc_field(desc));
+
+    let libs = libraries(module)
+        .iter()
+        .map(|lib| format!(" -l{}", pc_escaped(lib)))
+        .collect::<Vec<String>>()
+        .concat();
+
+    let cflags = include_dirs(module)
+        .iter()
+        .map(|dir| format!(" -I${{prefix}}/{}", pc_escaped(dir)))
+        .collect::<Vec<String>>()
+        .concat();
+
+    format!(
+        "# {HEADER}\nprefix={}\nlibdir=${{prefix}}/{}\n\nName: {}\nDescription: {description}\nVersion: {}\nLibs: -L${{libdir}}{libs}\nCflags:{cflags}\n",
+        pc_escaped(&prefix.to_string_lossy()),
+        lib_dir(prefix),
+        pc_field(&module.name),
+        pc_field(&module.version),
+    )
+}
+
+/// Generate a cmake package configuration file for `module` installed in
+/// `prefix`. It sets `<name>_INCLUDE_DIRS` and `<name>_LIBRARIES` and defines
+/// the imported target `<name>::<name>`.
+///
+/// `<name>_FOUND` is only set if every library and include directory is
+/// found, with `<name>_NOT_FOUND_MESSAGE` explaining what is missing.
+#[must_use]
+pub fn cmake_config(module: &Module, prefix: &Path) -> String {
+    let name = &module.name;
+    let lib_dir = prefix.join(lib_dir(prefix));
+
+    let include_dirs = include_dirs(module)
+        .iter()
+        .map(|dir| cmake_quoted(&prefix.join(dir).to_string_lossy()))
+        .collect::<Vec<String>>()
+        .join(";");
+
+    let libraries = libraries(module)
+        .iter()
+        .map(|lib| format!("\"{}\"", cmake_quoted(lib)))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    format!(
+        r#"# {HEADER}
+set({name}_VERSION "{version}")
+set({name}_INCLUDE_DIRS "{include_dirs}")
+set({name}_LIBRARIES)
+set({name}_FOUND TRUE)
+
+foreach(_sccmod_lib {libraries})
+    find_library({name}_${{_sccmod_lib}}_LIBRARY
+        NAMES ${{_sccmod_lib}}
+        PATHS "{lib_dir}"
+        NO_DEFAULT_PATH)
+
+    if({name}_${{_sccmod_lib}}_LIBRARY)
+        list(APPEND {name}_LIBRARIES "${{{name}_${{_sccmod_lib}}_LIBRARY}}")
+    else()
+        set({name}_FOUND FALSE)
+        set({name}_NOT_FOUND_MESSAGE "Library ${{_sccmod_lib}} not found in {lib_dir}")
+    endif()
+endforeach()
+
+foreach(_sccmod_dir IN LISTS {name}_INCLUDE_DIRS)
+    if(NOT IS_DIRECTORY "${{_sccmod_dir}}")
+        set({name}_FOUND FALSE)
+        set({name}_NOT_FOUND_MESSAGE "Include directory ${{_sccmod_dir}} not found")
+    endif()
+endforeach()
+
+if({name}_FOUND AND NOT TARGET {name}::{name})
+    add_library({name}::{name} INTERFACE IMPORTED)
+    set_target_properties({name}::{name} PROPERTIES
+        INTERFACE_INCLUDE_DIRECTORIES "${{{name}_INCLUDE_DIRS}}"
+        INTERFACE_LINK_LIBRARIES "${{{name}_LIBRARIES}}")
+endif()
+"#,
+        version = cmake_quoted(&module.version),
+        lib_dir = cmake_quoted(&lib_dir.to_string_lossy()),
+    )
+}
+
+/// Generate the cmake version file accompanying [`cmake_config`], accepting
+/// requests for the version of `module` or any older one
+#[must_use]
+pub fn cmake_version(module: &Module) -> String {
+    format!(
+        r#"# {HEADER}
+set(PACKAGE_VERSION "{}")
+
+if(PACKAGE_FIND_VERSION VERSION_GREATER PACKAGE_VERSION)
+    set(PACKAGE_VERSION_COMPATIBLE FALSE)
+else()
+    set(PACKAGE_VERSION_COMPATIBLE TRUE)
+    if(PACKAGE_FIND_VERSION VERSION_EQUAL PACKAGE_VERSION)
+        set(PACKAGE_VERSION_EXACT TRUE)
+    endif()
+endif()
+"#,
+        cmake_quoted(&module.version)
+    )
+}
+
+/// Write `content` to `path`, unless the build installed a file there itself
+fn write(path: &Path, content: &str) -> Result<(), String> {
+    let installed = std::fs::read_to_string(path).is_ok_and(|existing| {
+        !existing.lines().next().unwrap_or_default().contains(HEADER)
+    });
+
+    if installed {
+        log::info(&format!(
+            "Keeping {} installed by the build",
+            path.display()
+        ));
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(path.parent().unwrap_or(path))
+        .and_then(|()| std::fs::write(path, content))
+        .map_err(|err| format!("Failed to write {}: {err}", path.display()))
+}
+
+/// Write a pkg-config file and a cmake package configuration for `module`
+/// into `prefix`, if it declares the libraries it installs in its `libs`
+/// metadata. Files installed by the build itself are kept.
+///
+/// The files are placed where dependents find them through `PKG_CONFIG_PATH`
+/// and `CMAKE_PREFIX_PATH`: `lib/pkgconfig/<name>.pc` and
+/// `lib/cmake/<name>/<name>Config.cmake`.
+///
+/// # Errors
+/// Errors if a file cannot be written.
+pub fn generate(module: &Module, prefix: &Path) -> Result<(), String> {
+    if libraries(module).is_empty() {
+        return Ok(());
+    }
+
+    let lib_dir: PathBuf = prefix.join(lib_dir(prefix));
+    let cmake_dir = lib_dir.join("cmake").join(&module.name);
+
+    log::status(&format!(
+        "Writing pkg-config and CMake files for {}",
+        module.mod_name()
+    ));
+
+    write(
+        &lib_dir.join("pkgconfig").join(format!("{}.pc", module.name)),
+        &pkg_config(module, prefix),
+    )?;
+    write(
+        &cmake_dir.join(format!("{}Config.cmake", module.name)),
+        &cmake_config(module, prefix),
+    )?;
+    write(
+        &cmake_dir.join(format!("{}ConfigVersion.cmake", module.name)),
+        &cmake_version(module),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn module(name: &str) -> Module {
+        let mut module = testing::module(
+            "/opt",
+            &format!("libraries/{name}/1.14"),
+            Vec::new(),
+        );
+        module.metadata = [
+            ("libs".to_string(), "hdf5, hdf5_hl".to_string()),
+            ("description".to_string(), "HDF5 costs $0\nto use".to_string()),
+        ]
+        .into();
+        module
+    }
+
+    #[test]
+    fn pkg_config_file() {
+        assert_eq!(
+            pkg_config(&module("hdf$5"), Path::new("/opt/hdf 5")),
+            "# GENERATED BY SCCMOD
+prefix=/opt/hdf\\ 5
+libdir=${prefix}/lib
+
+Name: hdf$$5
+Description: HDF5 costs $$0 to use
+Version: 1.14
+Libs: -L${libdir} -lhdf5 -lhdf5_hl
+Cflags: -I${prefix}/include
+"
+        );
+    }
+
+    #[test]
+    fn cmake_found_follows_results() {
+        let config = cmake_config(&module("hdf5"), Path::new("/opt/hdf5"));
+
+        assert!(
+            config.contains(r#"set(hdf5_INCLUDE_DIRS "/opt/hdf5/include")"#)
+        );
+        assert!(config.contains("foreach(_sccmod_lib \"hdf5\" \"hdf5_hl\")"));
+        assert!(config.contains("PATHS \"/opt/hdf5/lib\""));
+
+        // Found unless a library or include directory is missing
+        assert!(config.contains("set(hdf5_FOUND TRUE)\n\nforeach"));
+        assert_eq!(config.matches("set(hdf5_FOUND FALSE)").count(), 2);
+        assert!(config.contains("if(hdf5_FOUND AND NOT TARGET hdf5::hdf5)"));
+    }
+
+    #[test]
+    fn build_installed_files_are_kept() {
+        let dir = testing::temp_dir("package-config");
+        let prefix = dir.join("prefix");
+        let pc = prefix.join("lib/pkgconfig/hdf5.pc");
+        let cmake = prefix.join("lib/cmake/hdf5/hdf5Config.cmake");
+
+        std::fs::create_dir_all(pc.parent().unwrap()).unwrap();
+        std::fs::write(&pc, "prefix=/build\n").unwrap();
+
+        generate(&module("hdf5"), &prefix).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&pc).unwrap(), "prefix=/build\n");
+        assert!(std::fs::read_to_string(&cmake)
+            .unwrap()
+            .starts_with("# GENERATED BY SCCMOD"));
+
+        // Generated files are replaced
+        std::fs::write(&cmake, "# GENERATED BY SCCMOD\nold\n").unwrap();
+        generate(&module("hdf5"), &prefix).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&cmake).unwrap(),
+            cmake_config(&module("hdf5"), &prefix)
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
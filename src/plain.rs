@@ -0,0 +1,285 @@
+use std::path::Path;
+
+use crate::{
+    config,
+    database::{self, InstallRecord},
+    environment,
+    escape::{tcl_quoted, tcl_word},
+    file_manager::PATH_SEP,
+    flavours,
+    module::{Dependency, Environment, Module},
+    modulefile::{delimiter_option, help, metadata_comments, providers},
+    template,
+};
+
+/// The modulefile name of `flavour` of `module`: its version, followed by the
+/// flavour, e.g. `0.3.28-gcc-13.1-openmpi-5.0.3`
+fn flavour_version(module: &Module, flavour: &(Vec<Module>, usize)) -> String {
+    if flavour.1 == 0 {
+        return module.version.clone();
+    }
+
+    let (flavour_str, _, _, _) = module.parse(&(&flavour.0, flavour.1));
+    let flavour_dir = flavour_str.rsplit(PATH_SEP).next().unwrap_or_default();
+
+    format!("{}-{flavour_dir}", module.version)
+}
+
+/// The path of the plain Tcl modulefile for `flavour` of `module`
+#[must_use]
+pub fn path(
+    module: &Module,
+    flavour: &(Vec<Module>, usize),
+    config: &config::Config,
+) -> String {
+    format!(
+        "{}{PATH_SEP}{}{PATH_SEP}{}{PATH_SEP}{}",
+        config.modulefile_root,
+        module.class,
+        module.name,
+        flavour_version(module, flavour)
+    )
+}
+
+/// The modulefile name of `dependency` matching `flavour`: the flavour of
+/// `dependency` built against the same modules, e.g. `openmpi/5.0.3-gcc-13.1`
+/// for a module built with `gcc/13.1` and `openmpi/5.0.3`
+fn flavoured_name(
+    dependency: &Module,
+    flavour: &(Vec<Module>, usize),
+    modules: &[Module],
+) -> String {
+    let in_flavour =
+        |m: &Module| flavour.0.iter().any(|f| f.identifier() == m.identifier());
+
+    flavours::generate_with(dependency, modules)
+        .unwrap_or_default()
+        .iter()
+        .find(|flav| flav.0.iter().take(flav.1).all(in_flavour))
+        .map_or_else(
+            || dependency.mod_name(),
+            |flav| {
+                format!(
+                    "{}/{}",
+                    dependency.name,
+                    flavour_version(dependency, flav)
+                )
+            },
+        )
+}
+
+/// The names of the modules `module` cannot be loaded alongside: the other
+/// members of its class, and the other providers of its virtuals
+fn conflicts(
+    module: &Module,
+    modules: &[Module],
+    config: &config::Config,
+) -> Vec<String> {
+    let mut names: Vec<&str> = Vec::new();
+
+    if !config.class_no_conflict.contains(&module.class) {
+        names.extend(
+            modules
+                .iter()
+                .filter(|m| m.class == module.class)
+                .map(|m| m.name.as_str()),
+        );
+    }
+
+    for virtual_name in &module.provides {
+        names.extend(providers(modules, virtual_name));
+    }
+
+    names.sort_unstable();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter(|name| *name != module.name)
+        .map(|name| format!("conflict {}\n", tcl_word(name)))
+        .collect()
+}
+
+/// The built-in template of plain Tcl modulefiles. See [`generate`] for the
+/// placeholders available.
+pub const TEMPLATE: &str = r#"#%Module
+# MODULEFILE GENERATED BY SCCMOD
+# https://github.com/Pencilcaseman/sccmod
+
+# Metadata
+@metadata@
+# Module help
+proc ModulesHelp { } {
+   puts stderr "
+@help@"
+}
+
+module-whatis "@description@"
+
+# Modules this flavour was built against
+@prerequisites@
+# Load submodules
+@dependencies@
+# Conflict with other modules of the same class
+@conflict@
+# Set environment variables
+@environment@"#;
+
+/// The Tcl command applying `value` to `key`, with relative paths resolved
+/// against `root_dir`
+#[must_use]
+pub fn environment_command(
+    key: &str,
+    value: &Environment,
+    root_dir: &str,
+) -> String {
+    let key = tcl_quoted(key);
+    let path = |val: &str| {
+        if val.is_empty() {
+            tcl_quoted(root_dir)
+        } else if Path::new(val).is_absolute() {
+            tcl_quoted(val)
+        } else {
+            tcl_quoted(&format!("{root_dir}{PATH_SEP}{val}"))
+        }
+    };
+
+    match value {
+        Environment::Set(val) => {
+            format!("setenv \"{key}\" \"{}\"\n", path(val))
+        }
+        Environment::SetExact(val) => {
+            format!("setenv \"{key}\" \"{}\"\n", tcl_quoted(val))
+        }
+        Environment::Append(val, sep) => format!(
+            "append-path{} \"{key}\" \"{}\"\n",
+            delimiter_option(sep.as_ref()),
+            path(val)
+        ),
+        Environment::Prepend(val, sep) => format!(
+            "prepend-path{} \"{key}\" \"{}\"\n",
+            delimiter_option(sep.as_ref()),
+            path(val)
+        ),
+        Environment::RemovePath(val, sep) => format!(
+            "remove-path{} \"{key}\" \"{}\"\n",
+            delimiter_option(sep.as_ref()),
+            path(val)
+        ),
+        Environment::Unset => format!("unsetenv \"{key}\"\n"),
+        Environment::SetAlias(body) => {
+            format!("set-alias \"{key}\" \"{}\"\n", tcl_quoted(body))
+        }
+        Environment::SetFunction(body) => {
+            format!("set-function \"{key}\" \"{}\"\n", tcl_quoted(body))
+        }
+        Environment::SourceSh(script) => {
+            format!("source-sh \"{key}\" \"{}\"\n", path(script))
+        }
+    }
+}
+
+/// Generate a standalone Tcl modulefile loading `flavour` of `module`, with
+/// any environment detected in `records` of the flavour. The help text lists
+/// the `installed` flavours.
+///
+/// Unlike [`crate::modulefile::generate`], the result does not need the
+/// `flavours` Tcl extension: the flavour is fixed, the modules it was built
+/// against are explicit prerequisites and all paths are absolute.
+///
+/// The modulefile is rendered from the module's template, or [`TEMPLATE`],
+/// with the placeholders `@name@`, `@version@`, `@class@`, `@metadata@`,
+/// `@help@`, `@description@`, `@prerequisites@`, `@dependencies@`,
+/// `@conflict@`, `@root@`, `@revision@` and `@environment@`. `@help@` and
+/// `@description@` are escaped for a double-quoted string. `@name@`,
+/// `@version@`, `@class@` and `@root@` are escaped for a bare word, which is
+/// also valid inside a double-quoted string.
+///
+/// # Errors
+/// Errors if a versioned dependency cannot be resolved or the template cannot
+/// be read.
+pub fn generate(
+    module: &Module,
+    flavour: &(Vec<Module>, usize),
+    modules: &[Module],
+    records: &[InstallRecord],
+    installed: &[String],
+    config: &config::Config,
+) -> Result<String, String> {
+    let module_metadata = metadata_comments(module, "# ");
+
+    let module_help = tcl_quoted(&help(module, installed));
+
+    let no_description_provided = "No description provided".to_string();
+    let module_description = tcl_quoted(
+        module.metadata.get("description").unwrap_or(&no_description_provided),
+    );
+
+    let prerequisites = flavour
+        .0
+        .iter()
+        .take(flavour.1)
+        .map(|m| {
+            format!(
+                "prereq {}\n",
+                tcl_word(&flavoured_name(m, flavour, modules))
+            )
+        })
+        .collect::<Vec<String>>()
+        .concat();
+
+    let dependent_modules = module
+        .dependencies
+        .iter()
+        .filter_map(|dep| match dep {
+            Dependency::Depends(name) => Some(name),
+            _ => None,
+        })
+        .map(|name| {
+            // Modules not managed by sccmod are loaded by name
+            let name = match flavours::resolve_dependency(modules, name) {
+                Ok(dependency) => flavoured_name(dependency, flavour, modules),
+                Err(_) if !name.contains('@') => name.to_owned(),
+                Err(err) => return Err(err),
+            };
+
+            Ok(format!("depends-on {}\n", tcl_word(&name)))
+        })
+        .collect::<Result<String, String>>()?;
+
+    let conflicts = conflicts(module, modules, config).concat();
+
+    let module = database::at_latest_revision(records, module, flavour);
+    let (_, _, root_dir, _) = module.parse(&(&flavour.0, flavour.1));
+
+    let environment_variables = environment::resolve(
+        &module,
+        records,
+        Some(&flavours::gen_name(flavour)),
+    )
+    .iter()
+    .map(|(key, value)| environment_command(key, value, &root_dir))
+    .collect::<Vec<String>>()
+    .concat();
+
+    let template = template::load(&module, config)?;
+    let revision = module.revision.to_string();
+
+    Ok(template::render(
+        template.as_deref().unwrap_or(TEMPLATE),
+        &[
+            ("name", &tcl_word(&module.name)),
+            ("version", &tcl_word(&module.version)),
+            ("class", &tcl_word(&module.class)),
+            ("metadata", &module_metadata),
+            ("help", &module_help),
+            ("description", &module_description),
+            ("prerequisites", &prerequisites),
+            ("dependencies", &dependent_modules),
+            ("conflict", &conflicts),
+            ("root", &tcl_word(&root_dir)),
+            ("revision", &revision),
+            ("environment", &environment_variables),
+        ],
+    ))
+}
@@ -2,6 +2,67 @@ use std::{fs::read_to_string, path::Path};
 
 use pyo3::prelude::*;
 
+/// The submodules of the `sccmod` Python package, embedded in the binary so
+/// module definitions can be loaded without access to the source tree sccmod
+/// was compiled from.
+///
+/// Submodules are loaded in order, so a submodule may only import from those
+/// listed before it.
+const PYTHON_SUBMODULES: [(&str, &str); 4] = [
+    ("env", include_str!("sccmod/env.py")),
+    ("builders", include_str!("sccmod/builders.py")),
+    ("downloaders", include_str!("sccmod/downloaders.py")),
+    ("module", include_str!("sccmod/module.py")),
+];
+
+/// The `__init__.py` of the `sccmod` Python package, run after every submodule
+/// has been loaded.
+const PYTHON_INIT: &str = include_str!("sccmod/__init__.py");
+
+/// Register the embedded `sccmod` Python package with the interpreter.
+///
+/// This makes `import sccmod` and `from sccmod.<submodule> import ...`
+/// available to module definitions. Calling this more than once has no effect.
+///
+/// # Errors
+/// Errors if the embedded package cannot be loaded. This is likely an internal
+/// error.
+pub fn register_package(py: Python) -> Result<(), String> {
+    let modules = py
+        .import_bound("sys")
+        .and_then(|sys| sys.getattr("modules"))
+        .map_err(|err| format!("Failed to access `sys.modules`: {err}"))?;
+
+    if modules.contains("sccmod").unwrap_or(false) {
+        return Ok(());
+    }
+
+    // Register an empty package first, so submodules can import each other
+    let package = PyModule::new_bound(py, "sccmod")
+        .and_then(|package| {
+            package.setattr("__path__", Vec::<String>::new())?;
+            modules.set_item("sccmod", &package)?;
+            Ok(package)
+        })
+        .map_err(|err| format!("Failed to create `sccmod` package: {err}"))?;
+
+    for (name, code) in PYTHON_SUBMODULES {
+        PyModule::from_code_bound(
+            py,
+            code,
+            &format!("sccmod/{name}.py"),
+            &format!("sccmod.{name}"),
+        )
+        .and_then(|submodule| package.setattr(name, submodule))
+        .map_err(|err| {
+            format!("Failed to load embedded module 'sccmod.{name}': {err}")
+        })?;
+    }
+
+    py.run_bound(PYTHON_INIT, Some(&package.dict()), None)
+        .map_err(|err| format!("Failed to initialise `sccmod`: {err}"))
+}
+
 /// Load a Python program from a file path.
 ///
 /// # Errors
@@ -11,11 +72,9 @@ pub fn load_program<'a, P: AsRef<Path> + std::fmt::Debug>(
     py: &'a Python,
     path: &P,
 ) -> Result<Bound<'a, PyModule>, String> {
-    let mut source_dir: String = env!("CARGO_MANIFEST_DIR").into();
-    source_dir.push_str("/src");
+    register_package(*py)?;
 
-    let file = read_to_string(path).map_err(|err| err.to_string())?;
-    let code = format!("import sys\nsys.path.append('{source_dir}')\n{file}");
+    let code = read_to_string(path).map_err(|err| err.to_string())?;
 
     PyModule::from_code_bound(*py, &code, "", "").map_err(|err| {
         format!("Failed to load python program '{path:?}': {err}")
@@ -1 +1 @@
-
+from sccmod.module import Module
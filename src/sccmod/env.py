@@ -2,3 +2,8 @@ SET = "set"
 SET_EXACT = "setexact"
 APPEND = "append"
 PREPEND = "prepend"
+REMOVE_PATH = "removepath"
+UNSET = "unset"
+SET_ALIAS = "setalias"
+SET_FUNCTION = "setfunction"
+SOURCE_SH = "sourcesh"
@@ -1,3 +1,147 @@
+from sccmod import env
+
+
+class Module:
+    """
+    Base class for sccmod module definitions.
+
+    Subclasses set `name`, `version` and `module_class` (and optionally
+    `description`) and override `download` and `build`. Every other method has
+    a sensible default, so only the parts that differ need to be written.
+
+    `revision` may be set to pin the install revision. Otherwise, a new
+    revision is installed whenever the build configuration changes.
+
+    `auto_environment` may be set to True to prepend the standard directories
+    found in the install prefix (`bin`, `lib`, `include`, `lib/pkgconfig`, ...)
+    to the matching variables, in addition to `environment`. If unset, the
+    `auto_environment` configuration option is used.
+
+    `modulefile_template` may be set to the path of a template replacing the
+    configured `modulefile_template` for this module.
+
+    `default` may be set to True to make this the version loaded when only the
+    module name is given, e.g. `module load openblas`.
+
+    `libs` may be set to the libraries the module installs, as passed to `-l`
+    (e.g. `["hdf5", "hdf5_hl"]`), and `includes` to its include directories
+    relative to the install prefix (`["include"]` if unset). After installing,
+    `<name>.pc` and `<name>Config.cmake` are then written to the prefix, unless
+    the build installed its own.
+    """
+
+    name = None
+    version = None
+    module_class = None
+    description = "No description provided"
+    revision = None
+    auto_environment = None
+    modulefile_template = None
+    default = False
+    libs = None
+    includes = None
+
+    def metadata(self):
+        for attribute in ("name", "version", "module_class"):
+            if not getattr(self, attribute, None):
+                raise ValueError(
+                    f"{type(self).__name__} does not set `{attribute}`"
+                )
+
+        metadata = {
+            "name": str(self.name),
+            "version": str(self.version),
+            "class": str(self.module_class),
+            "description": str(self.description),
+        }
+
+        if self.revision is not None:
+            metadata["revision"] = str(self.revision)
+
+        for key in ("libs", "includes"):
+            value = getattr(self, key)
+            if value is not None:
+                metadata[key] = (
+                    value if isinstance(value, str) else " ".join(value)
+                )
+
+        return metadata
+
+    def dependencies(self):
+        return []
+
+    def provides(self):
+        return []
+
+    def environment(self):
+        return []
+
+    def download(self):
+        return None
+
+    def build(self):
+        return None
+
+    def pre_build(self):
+        return None
+
+    def post_install(self):
+        return None
+
+    # Environment helpers. Paths are relative to the install prefix of the
+    # flavour being loaded.
+
+    @staticmethod
+    def set_path(variable, path=""):
+        return (variable, (env.SET, path))
+
+    # Path lists are separated by `separator`, or ":" if it is None.
+
+    @staticmethod
+    def prepend_path(variable, path, separator=None):
+        return (variable, _path_operation(env.PREPEND, path, separator))
+
+    @staticmethod
+    def append_path(variable, path, separator=None):
+        return (variable, _path_operation(env.APPEND, path, separator))
+
+    @staticmethod
+    def remove_path(variable, path, separator=None):
+        return (variable, _path_operation(env.REMOVE_PATH, path, separator))
+
+    @staticmethod
+    def set_exact(variable, value):
+        return (variable, (env.SET_EXACT, str(value)))
+
+    @staticmethod
+    def unset(variable):
+        return (variable, (env.UNSET, ""))
+
+    @staticmethod
+    def set_alias(name, body):
+        return (name, (env.SET_ALIAS, body))
+
+    @staticmethod
+    def set_function(name, body):
+        return (name, (env.SET_FUNCTION, body))
+
+    # Apply the changes a vendor script, such as oneAPI's `setvars.sh`, makes
+    # to the environment when sourced by `shell`
+    @staticmethod
+    def source_sh(script, shell="bash"):
+        return (shell, (env.SOURCE_SH, script))
+
+    def __repr__(self):
+        return f"{type(self).__name__}({self.name}/{self.version})"
+
+
+def _path_operation(operation, path, separator):
+    if separator is None:
+        return (operation, path)
+
+    return (operation, path, separator)
+
+
 class Class:
     def __init__(self, name):
         self.name = name
@@ -9,6 +153,22 @@ class Class:
         return self.name
 
 
+class Virtual:
+    """
+    Depend on any module which `provides` the named virtual package, such as
+    "blas" or "mpi". Like `Class`, a flavour is built for each provider.
+    """
+
+    def __init__(self, name):
+        self.name = name
+
+    def __str__(self):
+        return self.name
+
+    def __repr__(self):
+        return f"Virtual({self.name})"
+
+
 class Deny:
     def __init__(self, name):
         if isinstance(name, (list, tuple)):
@@ -23,6 +183,46 @@ class Deny:
         return str(self.name.split(":"))
 
 
+class Require:
+    """
+    Only build flavours containing the named module. If a list is given, the
+    flavour must contain at least one of them.
+    """
+
+    def __init__(self, name):
+        if isinstance(name, (list, tuple)):
+            self.name = ":".join(name)
+        else:
+            self.name = name
+
+    def __str__(self):
+        return str(self.name.split(":"))
+
+    def __repr__(self):
+        return f"Require({self.name.split(':')})"
+
+
+class OnlyWith:
+    """
+    Only build flavours containing `name` if they also contain one of
+    `partners`. For example, `OnlyWith("intel", "intelmpi")` drops flavours
+    combining the Intel compiler with any other MPI.
+    """
+
+    def __init__(self, name, partners):
+        self.name = name
+        if isinstance(partners, (list, tuple)):
+            self.partners = ":".join(partners)
+        else:
+            self.partners = partners
+
+    def __str__(self):
+        return f"{self.name} -> {self.partners.split(':')}"
+
+    def __repr__(self):
+        return f"OnlyWith({self.name}, {self.partners.split(':')})"
+
+
 class Depends:
     def __init__(self, name):
         if isinstance(name, (list, tuple)):
@@ -0,0 +1,238 @@
+use std::path::Path;
+
+use crate::{
+    config,
+    database::InstallRecord,
+    downloaders::DownloaderImpl,
+    flavours, hash,
+    module::Module,
+    modulefile::{self, Output},
+};
+
+/// The state of the modulefile of a module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModulefileState {
+    Missing,
+    Outdated,
+    UpToDate,
+}
+
+/// The download, build and install state of a single flavour of a module
+#[derive(Debug, Clone)]
+pub struct FlavourStatus {
+    pub flavour: String,
+
+    /// The commit, hash or URL of the downloaded source, if downloaded
+    pub downloaded: Option<String>,
+
+    pub built: bool,
+
+    /// The most recent install record of this flavour, at any revision
+    pub installed: Option<InstallRecord>,
+
+    pub modulefile: ModulefileState,
+
+    /// Reasons the installed flavour is out of date
+    pub stale: Vec<String>,
+}
+
+impl FlavourStatus {
+    /// Returns `true` if the flavour is installed at the current revision of
+    /// its module
+    #[must_use]
+    pub fn is_current(&self, module: &Module) -> bool {
+        self.installed
+            .as_ref()
+            .is_some_and(|rec| rec.revision == module.revision)
+    }
+}
+
+/// Work out the state of the modulefiles loading `flavour` by comparing the
+/// files on disk with freshly generated ones. Flavours without a generated
+/// modulefile are missing one.
+#[must_use]
+pub fn modulefile_state(outputs: &[Output], flavour: &str) -> ModulefileState {
+    let covering: Vec<&Output> =
+        outputs.iter().filter(|output| output.covers(flavour)).collect();
+
+    if covering.is_empty() {
+        return ModulefileState::Missing;
+    }
+
+    let mut state = ModulefileState::UpToDate;
+
+    for output in covering {
+        match std::fs::read_to_string(&output.path) {
+            Err(_) => return ModulefileState::Missing,
+            Ok(content) if content != output.content => {
+                state = ModulefileState::Outdated;
+            }
+            Ok(_) => {}
+        }
+    }
+
+    state
+}
+
+/// Find the reasons an installed flavour is out of date: the module
+/// definition changed, a prerequisite was installed after it, or the
+/// modulefile was not rewritten after it was installed.
+fn stale_reasons(
+    module: &Module,
+    record: &InstallRecord,
+    flavour: &(Vec<Module>, usize),
+    records: &[InstallRecord],
+) -> Vec<String> {
+    let mut reasons = Vec::new();
+
+    if record.fingerprint != module.fingerprint() {
+        reasons.push("definition changed".to_string());
+    }
+
+    for dep in &flavour.0 {
+        let newer = records.iter().any(|rec| {
+            rec.is_for(dep) && rec.installed_at > record.installed_at
+        });
+
+        if newer {
+            reasons.push(format!("{} reinstalled", dep.mod_name()));
+        }
+    }
+
+    if record
+        .modulefile_written_at
+        .is_none_or(|written_at| written_at < record.installed_at)
+    {
+        reasons.push("modulefile not rewritten".to_string());
+    }
+
+    reasons
+}
+
+/// Collect the status of every flavour of `module`.
+///
+/// # Errors
+/// Errors if the flavours or modulefiles of `module` cannot be generated.
+pub fn collect(
+    module: &Module,
+    modules: &[Module],
+    records: &[InstallRecord],
+    config: &config::Config,
+) -> Result<Vec<FlavourStatus>, String> {
+    let downloaded = module.downloader.as_ref().and_then(|downloader| {
+        Path::new(&module.source_path)
+            .exists()
+            .then(|| downloader.source_revision(&module.source_path))
+            .map(Option::unwrap_or_default)
+    });
+
+    // Compare against every flavour, as modulefiles of flavours which are not
+    // installed may have been written with --force
+    let outputs = modulefile::outputs(module, modules, config, true)?;
+
+    Ok(flavours::generate_with(module, modules)?
+        .iter()
+        .map(|flav| {
+            let (_, build_path, _, _) = module.parse(&(&flav.0, flav.1));
+            let name = flavours::gen_name(flav);
+
+            let installed = records
+                .iter()
+                .filter(|rec| rec.is_for(module) && rec.flavour == name)
+                .filter(|rec| Path::new(&rec.install_path).exists())
+                .max_by_key(|rec| rec.revision)
+                .cloned();
+
+            let mut stale = installed.as_ref().map_or_else(Vec::new, |rec| {
+                stale_reasons(module, rec, flav, records)
+            });
+
+            if installed.as_ref().is_some_and(|rec| {
+                rec.revision < module.revision && stale.is_empty()
+            }) {
+                stale.push(format!("revision {} pending", module.revision));
+            }
+
+            FlavourStatus {
+                modulefile: modulefile_state(&outputs, &name),
+                flavour: name,
+                downloaded: downloaded.clone(),
+                built: Path::new(&build_path).exists(),
+                installed,
+                stale,
+            }
+        })
+        .collect())
+}
+
+/// A problem with a modulefile found by [`check`]
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub path: String,
+    pub problem: String,
+}
+
+/// Find the modulefiles of `module` which were edited by hand since they were
+/// generated, or which load an install path that no longer exists.
+///
+/// Both the modulefiles `module` would generate now for any flavour and those
+/// noted in its install `records` are checked.
+///
+/// # Errors
+/// Errors if the modulefiles of `module` cannot be generated.
+pub fn check(
+    module: &Module,
+    modules: &[Module],
+    records: &[InstallRecord],
+    config: &config::Config,
+) -> Result<Vec<Problem>, String> {
+    let records: Vec<&InstallRecord> =
+        records.iter().filter(|rec| rec.is_for(module)).collect();
+
+    let mut paths: Vec<String> =
+        modulefile::outputs(module, modules, config, true)?
+            .into_iter()
+            .map(|output| output.path)
+            .chain(records.iter().filter_map(|rec| rec.modulefile.clone()))
+            .filter(|path| Path::new(path).exists())
+            .collect();
+
+    paths.sort();
+    paths.dedup();
+
+    let mut problems = Vec::new();
+
+    for path in paths {
+        let content = std::fs::read_to_string(&path).map_err(|err| {
+            format!("Failed to read modulefile {path}: {err}")
+        })?;
+
+        let problem = match modulefile::unseal(&content) {
+            (None, _) => Some("no sccmod hash in the header".to_string()),
+            (Some(hash), content) if hash != hash::hex_digest(&content) => {
+                Some("edited by hand since it was generated".to_string())
+            }
+            _ => None,
+        };
+
+        problems.extend(
+            problem.map(|problem| Problem { path: path.clone(), problem }),
+        );
+
+        problems.extend(
+            records
+                .iter()
+                .filter(|rec| rec.modulefile.as_ref() == Some(&path))
+                .filter(|rec| !Path::new(&rec.install_path).exists())
+                .map(|rec| Problem {
+                    path: path.clone(),
+                    problem: format!(
+                        "install path {} no longer exists",
+                        rec.install_path
+                    ),
+                }),
+        );
+    }
+
+    Ok(problems)
+}
@@ -0,0 +1,66 @@
+use crate::{config, module::Module};
+
+/// Replace each `@name@` placeholder in `template` with the value of `name`.
+///
+/// Placeholders without a value, and any `@` outside of one, are kept as they
+/// are, and substituted values are never expanded again.
+///
+/// ```
+/// use sccmod::template::render;
+///
+/// let rendered = render(
+///     "# @name@ (@email@)\nroot @root@\n",
+///     &[("name", "hello"), ("root", "/opt/@root@")],
+/// );
+/// assert_eq!(rendered, "# hello (@email@)\nroot /opt/@root@\n");
+/// ```
+#[must_use]
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut result: Vec<&str> = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('@') {
+        let after = &rest[start + 1..];
+
+        let placeholder = after.find('@').and_then(|end| {
+            values
+                .iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, value)| (end, *value))
+        });
+
+        if let Some((end, value)) = placeholder {
+            result.push(&rest[..start]);
+            result.push(value);
+            rest = &after[end + 1..];
+        } else {
+            result.push(&rest[..=start]);
+            rest = after;
+        }
+    }
+
+    result.push(rest);
+    result.concat()
+}
+
+/// Read the modulefile template for `module`: its own template if it declares
+/// one, otherwise the configured `modulefile_template`. [`None`] selects the
+/// built-in template of the modulefile format.
+///
+/// # Errors
+/// Errors if the template file cannot be read.
+pub fn load(
+    module: &Module,
+    config: &config::Config,
+) -> Result<Option<String>, String> {
+    module
+        .modulefile_template
+        .as_ref()
+        .or(config.modulefile_template.as_ref())
+        .map(|path| {
+            std::fs::read_to_string(path).map_err(|err| {
+                format!("Failed to read modulefile template '{path}': {err}")
+            })
+        })
+        .transpose()
+}
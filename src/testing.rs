@@ -0,0 +1,108 @@
+//! Fixtures shared by the unit tests
+
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    builders::{builder_trait::Builder, make::Make},
+    config::{Config, ModulefileFormat},
+    database::InstallRecord,
+    flavours,
+    module::{Dependency, Module},
+};
+
+/// An empty directory for the test `name`, under the system temporary
+/// directory
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("sccmod-test-{}-{name}", std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A Tcl configuration keeping every root below `root`
+pub fn config(root: &str) -> Config {
+    Config {
+        sccmod_module_paths: vec![format!("{root}/defs")],
+        modulefile_root: format!("{root}/modulefiles"),
+        build_root: format!("{root}/build"),
+        install_root: format!("{root}/install"),
+        shell: "bash".to_string(),
+        class_no_conflict: Vec::new(),
+        num_threads: 1,
+        default_flavours: HashMap::new(),
+        modulefile_format: ModulefileFormat::Tcl,
+        auto_environment: false,
+        modulefile_template: None,
+    }
+}
+
+/// A module with a builder and the given `dependencies`, laid out as
+/// [`config`] of `root` would place it
+pub fn module(
+    root: &str,
+    identifier: &str,
+    dependencies: Vec<Dependency>,
+) -> Module {
+    let mut parts = identifier.split('/');
+    let (class, name, version) = (
+        parts.next().unwrap().to_string(),
+        parts.next().unwrap().to_string(),
+        parts.next().unwrap().to_string(),
+    );
+
+    Module {
+        install_path: format!("{root}/install/{class}/{name}-{version}"),
+        source_path: format!("{root}/build/{name}/{version}"),
+        build_path: format!("{root}/build/{name}/{version}/sccmod_build"),
+        name,
+        version,
+        class,
+        dependencies,
+        provides: Vec::new(),
+        metadata: HashMap::new(),
+        environment: Vec::new(),
+        auto_environment: false,
+        modulefile_template: None,
+        default: false,
+        pre_build: None,
+        post_install: None,
+        downloader: None,
+        builder: Some(Builder::Make(Make {
+            configure: false,
+            jobs: None,
+            prefix_args: None,
+            configure_flags: None,
+            make_root: None,
+        })),
+        revision: 1,
+    }
+}
+
+/// A record of `flavour` of `module` installed at `revision`, creating its
+/// install directory
+pub fn record(
+    module: &Module,
+    flavour: &(Vec<Module>, usize),
+    revision: usize,
+) -> InstallRecord {
+    let module = Module { revision, ..module.clone() };
+    let (_, _, install_path, _) = module.parse(&(&flavour.0, flavour.1));
+    std::fs::create_dir_all(&install_path).unwrap();
+
+    InstallRecord {
+        identifier: module.identifier(),
+        flavour: flavours::gen_name(flavour),
+        dependencies: flavour.0.iter().map(Module::mod_name).collect(),
+        revision,
+        fingerprint: format!("fingerprint-{revision}"),
+        source_revision: None,
+        builder: "make".to_string(),
+        installed_at: 0,
+        install_path,
+        environment: Vec::new(),
+        modulefile: None,
+        modulefile_written_at: None,
+    }
+}
@@ -0,0 +1,417 @@
+use crate::config::ModulefileFormat;
+
+/// The line of `chars` containing position `pos`, counting from 1
+fn line(chars: &[char], pos: usize) -> usize {
+    chars.iter().take(pos).filter(|c| **c == '\n').count() + 1
+}
+
+/// A tokenizer following the Tcl parsing rules closely enough to find
+/// unbalanced braces, quotes and brackets
+struct Tcl {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Tcl {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn error(&self, start: usize, message: &str) -> String {
+        format!("line {}: {message}", line(&self.chars, start))
+    }
+
+    /// Parse commands up to the end of the script or, if `nested`, up to the
+    /// closing bracket of a command substitution
+    fn script(&mut self, nested: bool) -> Result<(), String> {
+        let start = self.pos;
+
+        loop {
+            while self.peek(0).is_some_and(|c| c.is_whitespace() || c == ';') {
+                self.pos += 1;
+            }
+
+            match self.peek(0) {
+                None if nested => {
+                    return Err(self.error(start, "missing close-bracket"))
+                }
+                None => return Ok(()),
+                Some(']') if nested => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some('#') => self.comment(),
+                Some(_) => {
+                    if self.command(nested)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Skip a comment, which ends at the first newline not escaped by a
+    /// backslash
+    fn comment(&mut self) {
+        while let Some(c) = self.peek(0) {
+            self.pos += 1;
+
+            match c {
+                '\\' => self.pos += 1,
+                '\n' => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// Parse the words of a single command. Returns `true` if the command
+    /// ended with the closing bracket of a `nested` script.
+    fn command(&mut self, nested: bool) -> Result<bool, String> {
+        loop {
+            loop {
+                match (self.peek(0), self.peek(1)) {
+                    (Some(' ' | '\t'), _) => self.pos += 1,
+                    (Some('\\'), Some('\n')) => self.pos += 2,
+                    _ => break,
+                }
+            }
+
+            match self.peek(0) {
+                None => return Ok(false),
+                Some('\n' | ';') => {
+                    self.pos += 1;
+                    return Ok(false);
+                }
+                Some(']') if nested => {
+                    self.pos += 1;
+                    return Ok(true);
+                }
+                Some('{') => self.braced(nested)?,
+                Some('"') => self.quoted(nested)?,
+                Some(_) => self.bare(nested)?,
+            }
+        }
+    }
+
+    /// A braced or quoted word must be followed by a word separator
+    fn word_end(&self, nested: bool, what: &str) -> Result<(), String> {
+        match self.peek(0) {
+            None | Some(' ' | '\t' | '\n' | ';') => Ok(()),
+            Some(']') if nested => Ok(()),
+            Some(_) => {
+                Err(self
+                    .error(self.pos, &format!("extra characters after {what}")))
+            }
+        }
+    }
+
+    fn braced(&mut self, nested: bool) -> Result<(), String> {
+        let start = self.pos;
+        let mut depth = 0;
+
+        while let Some(c) = self.peek(0) {
+            self.pos += 1;
+
+            match c {
+                '\\' => self.pos += 1,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        return self.word_end(nested, "close-brace");
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Err(self.error(start, "missing close-brace"))
+    }
+
+    fn quoted(&mut self, nested: bool) -> Result<(), String> {
+        let start = self.pos;
+        self.pos += 1;
+
+        while let Some(c) = self.peek(0) {
+            self.pos += 1;
+
+            match c {
+                '\\' => self.pos += 1,
+                '[' => self.script(true)?,
+                '"' => return self.word_end(nested, "close-quote"),
+                _ => {}
+            }
+        }
+
+        Err(self.error(start, "missing \""))
+    }
+
+    fn bare(&mut self, nested: bool) -> Result<(), String> {
+        while let Some(c) = self.peek(0) {
+            match c {
+                ' ' | '\t' | '\n' | ';' => return Ok(()),
+                ']' if nested => return Ok(()),
+                '\\' => self.pos += 2,
+                '[' => {
+                    self.pos += 1;
+                    self.script(true)?;
+                }
+                _ => self.pos += 1,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that `script` is a complete Tcl script, like Tcl's `info complete`,
+/// and that no braced or quoted word is followed by extra characters.
+///
+/// # Errors
+/// Errors with the line and nature of the first problem found.
+///
+/// ```
+/// use sccmod::validate::tcl;
+///
+/// assert!(tcl("proc help { } {\n    puts stderr \"a \\\"b\\\"\"\n}\n").is_ok());
+/// assert!(tcl("puts \"a \"b\"\"\n").is_err());
+/// assert!(tcl("if {1} {\n    puts [pwd]\n").is_err());
+/// ```
+pub fn tcl(script: &str) -> Result<(), String> {
+    Tcl { chars: script.chars().collect(), pos: 0 }.script(false)
+}
+
+/// The level of the Lua long bracket starting at `pos`, such as 0 for `[[`
+/// and 2 for `[==[`
+fn long_bracket(chars: &[char], pos: usize) -> Option<usize> {
+    if chars.get(pos) != Some(&'[') {
+        return None;
+    }
+
+    let level = chars[pos + 1..].iter().take_while(|c| **c == '=').count();
+
+    (chars.get(pos + level + 1) == Some(&'[')).then_some(level)
+}
+
+/// Find the end of the Lua long string or comment of `level` whose content
+/// starts at `pos`, returning the position after it
+fn long_end(chars: &[char], pos: usize, level: usize) -> Option<usize> {
+    let close: Vec<char> = std::iter::once(']')
+        .chain(std::iter::repeat_n('=', level))
+        .chain(std::iter::once(']'))
+        .collect();
+
+    chars[pos..]
+        .windows(close.len())
+        .position(|window| window == close.as_slice())
+        .map(|offset| pos + offset + close.len())
+}
+
+/// Check that the strings, comments and brackets of the Lua `script` are
+/// terminated and balanced.
+///
+/// # Errors
+/// Errors with the line and nature of the first problem found.
+///
+/// ```
+/// use sccmod::validate::lua;
+///
+/// assert!(lua("help([[\nsome ]=] text\n]])\nsetenv(\"A\", \"\\\"b\\\"\") -- (\n").is_ok());
+/// assert!(lua("setenv(\"A\", \"b)\n").is_err());
+/// assert!(lua("whatis(\"a\"\n").is_err());
+/// ```
+pub fn lua(script: &str) -> Result<(), String> {
+    let chars: Vec<char> = script.chars().collect();
+    let error = |pos: usize, message: &str| {
+        format!("line {}: {message}", line(&chars, pos))
+    };
+
+    let mut open: Vec<(char, usize)> = Vec::new();
+    let mut pos = 0;
+
+    while let Some(&c) = chars.get(pos) {
+        match c {
+            '-' if chars.get(pos + 1) == Some(&'-') => {
+                pos = match long_bracket(&chars, pos + 2) {
+                    Some(level) => long_end(&chars, pos + level + 4, level)
+                        .ok_or_else(|| error(pos, "unfinished long comment"))?,
+                    None => chars[pos..]
+                        .iter()
+                        .position(|c| *c == '\n')
+                        .map_or(chars.len(), |offset| pos + offset),
+                };
+            }
+            '[' if long_bracket(&chars, pos).is_some() => {
+                let level = long_bracket(&chars, pos).unwrap_or_default();
+
+                pos = long_end(&chars, pos + level + 2, level)
+                    .ok_or_else(|| error(pos, "unfinished long string"))?;
+            }
+            '"' | '\'' => {
+                let start = pos;
+                pos += 1;
+
+                loop {
+                    match chars.get(pos) {
+                        None | Some('\n') => {
+                            return Err(error(start, "unfinished string"))
+                        }
+                        Some('\\') => pos += 2,
+                        Some(q) if *q == c => break,
+                        Some(_) => pos += 1,
+                    }
+                }
+
+                pos += 1;
+            }
+            '(' | '{' | '[' => {
+                open.push((c, pos));
+                pos += 1;
+            }
+            ')' | '}' | ']' => {
+                let expected = match c {
+                    ')' => '(',
+                    '}' => '{',
+                    _ => '[',
+                };
+
+                match open.pop() {
+                    Some((opening, _)) if opening == expected => pos += 1,
+                    _ => return Err(error(pos, &format!("unexpected '{c}'"))),
+                }
+            }
+            _ => pos += 1,
+        }
+    }
+
+    open.pop()
+        .map_or(Ok(()), |(c, pos)| Err(error(pos, &format!("unclosed '{c}'"))))
+}
+
+/// Check a generated modulefile, with [`tcl`] or [`lua`] depending on its
+/// `format`.
+///
+/// # Errors
+/// Errors with the line and nature of the first problem found.
+pub fn modulefile(
+    content: &str,
+    format: ModulefileFormat,
+) -> Result<(), String> {
+    match format {
+        ModulefileFormat::Tcl | ModulefileFormat::Plain => tcl(content),
+        ModulefileFormat::Lua => lua(content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tcl_empty_script() {
+        assert!(tcl("").is_ok());
+        assert!(tcl("\n\n;\n").is_ok());
+    }
+
+    #[test]
+    fn tcl_unterminated_braces() {
+        assert_eq!(
+            tcl("proc a {} {\n    puts a\n").unwrap_err(),
+            "line 1: missing close-brace"
+        );
+        assert!(tcl("set a {{b}\n").is_err());
+        assert!(tcl("set a {b \\}\n").is_err());
+    }
+
+    #[test]
+    fn tcl_nested_and_escaped_braces() {
+        assert!(tcl("set a {b {c} d}\n").is_ok());
+        assert!(tcl("set a {b \\{ c}\n").is_ok());
+        assert!(tcl("set a \"\\{\"\n").is_ok());
+    }
+
+    #[test]
+    fn tcl_braces_in_comments() {
+        // Comments do not count outside a braced word, but do inside one
+        assert!(tcl("# {\nset a b\n").is_ok());
+        assert!(tcl("proc a {} {\n    # {\n}\n").is_err());
+    }
+
+    #[test]
+    fn tcl_comment_continued_by_backslash() {
+        assert!(tcl("# a \\\n{\nset a b\n").is_ok());
+    }
+
+    #[test]
+    fn tcl_unterminated_quotes_and_brackets() {
+        assert_eq!(tcl("\n\nputs \"a\n").unwrap_err(), "line 3: missing \"");
+        assert!(tcl("puts [pwd\n").is_err());
+        assert!(tcl("puts \"[pwd\"\n").is_err());
+        assert!(tcl("puts \"[string cat \"a\"]\"\n").is_ok());
+    }
+
+    #[test]
+    fn tcl_extra_characters() {
+        assert_eq!(
+            tcl("set a {b}c\n").unwrap_err(),
+            "line 1: extra characters after close-brace"
+        );
+        assert!(tcl("set a \"b\"c\n").is_err());
+        assert!(tcl("puts [list {a}]\n").is_ok());
+    }
+
+    #[test]
+    fn tcl_line_continuation() {
+        assert!(tcl("puts \\\n    a\n").is_ok());
+    }
+
+    #[test]
+    fn lua_empty_script() {
+        assert!(lua("").is_ok());
+        assert!(lua("-- only a comment").is_ok());
+    }
+
+    #[test]
+    fn lua_unterminated_long_strings() {
+        assert_eq!(
+            lua("help([[\ntext\n").unwrap_err(),
+            "line 1: unfinished long string"
+        );
+        assert!(lua("help([==[\ntext ]] ]=]\n").is_err());
+        assert!(lua("help([==[\ntext ]] ]=] ]==])\n").is_ok());
+    }
+
+    #[test]
+    fn lua_long_comments() {
+        assert!(lua("--[[ ( \" ]]\nsetenv(\"A\", \"b\")\n").is_ok());
+        assert!(lua("--[=[ ]]\n").is_err());
+        assert!(lua("-- [[ not long\n").is_ok());
+    }
+
+    #[test]
+    fn lua_quoted_strings() {
+        assert!(lua("a = 'it\\'s'\n").is_ok());
+        assert!(lua("a = \"(\"\n").is_ok());
+        assert!(lua("a = \"b\nc\"\n").is_err());
+        assert!(lua("a = 'b").is_err());
+    }
+
+    #[test]
+    fn lua_brackets() {
+        assert!(lua("a = t[b[1]]\n").is_ok());
+        assert!(lua("f({1, (2)})\n").is_ok());
+        assert_eq!(lua("f(1]\n").unwrap_err(), "line 1: unexpected ']'");
+        assert_eq!(lua("\nf(\n").unwrap_err(), "line 2: unclosed '('");
+        assert!(lua(")\n").is_err());
+    }
+
+    #[test]
+    fn modulefile_selects_the_format() {
+        let script = "x = {1}..y\n";
+
+        assert!(modulefile(script, ModulefileFormat::Tcl).is_err());
+        assert!(modulefile(script, ModulefileFormat::Plain).is_err());
+        assert!(modulefile(script, ModulefileFormat::Lua).is_ok());
+    }
+}
@@ -0,0 +1,356 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// A single component of a [`Version`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Number(u64),
+    Text(String),
+}
+
+/// A module version, such as `13.2.0`, `1.14.3-rc1` or `latest`.
+///
+/// Versions are split on `.`, `-` and `_`. Numeric segments are compared
+/// numerically and text segments lexically, with missing trailing segments
+/// treated as zero, so `13` and `13.0.0` are equal.
+///
+/// # Example
+/// ```rust
+/// use sccmod::version::Version;
+///
+/// let a: Version = "0.3.9".parse().unwrap();
+/// let b: Version = "0.3.28".parse().unwrap();
+/// assert!(a < b);
+/// assert_eq!("13".parse::<Version>(), "13.0.0".parse::<Version>());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub segments: Vec<Segment>,
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments: Vec<Segment> = s
+            .trim()
+            .split(['.', '-', '_'])
+            .map(|seg| {
+                if seg.is_empty() {
+                    Err(format!("Invalid version '{s}': empty segment"))
+                } else {
+                    Ok(seg.parse::<u64>().map_or_else(
+                        |_| Segment::Text(seg.to_lowercase()),
+                        Segment::Number,
+                    ))
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { segments })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let segments: Vec<String> = self
+            .segments
+            .iter()
+            .map(|seg| match seg {
+                Segment::Number(num) => num.to_string(),
+                Segment::Text(txt) => txt.clone(),
+            })
+            .collect();
+
+        write!(f, "{}", segments.join("."))
+    }
+}
+
+fn cmp_segment(lhs: Option<&Segment>, rhs: Option<&Segment>) -> Ordering {
+    const ZERO: Segment = Segment::Number(0);
+
+    match (lhs.unwrap_or(&ZERO), rhs.unwrap_or(&ZERO)) {
+        (Segment::Number(a), Segment::Number(b)) => a.cmp(b),
+        (Segment::Text(a), Segment::Text(b)) => a.cmp(b),
+        // A release (number) is considered newer than a tag (text)
+        (Segment::Number(_), Segment::Text(_)) => Ordering::Greater,
+        (Segment::Text(_), Segment::Number(_)) => Ordering::Less,
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.segments.len().max(other.segments.len());
+
+        (0..len)
+            .map(|i| cmp_segment(self.segments.get(i), other.segments.get(i)))
+            .find(|ord| ord.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Version {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+
+    /// Matches the version and anything more specific, e.g. `1.14` (or
+    /// `1.14.x`/`1.14.*`) matches `1.14`, `1.14.0` and `1.14.3`
+    Prefix,
+}
+
+/// A single version comparison, such as `>=12` or `1.14.x`
+#[derive(Debug, Clone)]
+pub struct Constraint {
+    pub comparator: Comparator,
+    pub version: Version,
+}
+
+impl Constraint {
+    #[must_use]
+    pub fn matches(&self, version: &Version) -> bool {
+        match self.comparator {
+            Comparator::Eq => version == &self.version,
+            Comparator::Ne => version != &self.version,
+            Comparator::Gt => version > &self.version,
+            Comparator::Ge => version >= &self.version,
+            Comparator::Lt => version < &self.version,
+            Comparator::Le => version <= &self.version,
+            Comparator::Prefix => {
+                self.version.segments.iter().enumerate().all(|(i, seg)| {
+                    cmp_segment(Some(seg), version.segments.get(i)).is_eq()
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let (comparator, version) = [
+            (">=", Comparator::Ge),
+            ("<=", Comparator::Le),
+            ("==", Comparator::Eq),
+            ("!=", Comparator::Ne),
+            (">", Comparator::Gt),
+            ("<", Comparator::Lt),
+            ("=", Comparator::Eq),
+        ]
+        .iter()
+        .find_map(|(op, cmp)| s.strip_prefix(op).map(|rest| (*cmp, rest)))
+        .unwrap_or((Comparator::Prefix, s));
+
+        let version = match comparator {
+            Comparator::Prefix => version
+                .strip_suffix(".x")
+                .or_else(|| version.strip_suffix(".*"))
+                .unwrap_or(version),
+            _ => version,
+        };
+
+        Ok(Self { comparator, version: version.parse()? })
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.comparator {
+            Comparator::Eq => "=",
+            Comparator::Ne => "!=",
+            Comparator::Gt => ">",
+            Comparator::Ge => ">=",
+            Comparator::Lt => "<",
+            Comparator::Le => "<=",
+            Comparator::Prefix => "",
+        };
+
+        write!(f, "{op}{}", self.version)
+    }
+}
+
+/// A comma-separated set of [`Constraint`]s, all of which must match.
+///
+/// # Example
+/// ```rust
+/// use sccmod::version::{Requirement, Version};
+///
+/// let req: Requirement = ">=12,<14".parse().unwrap();
+/// assert!(req.matches(&"13.2.0".parse::<Version>().unwrap()));
+/// assert!(!req.matches(&"14.1".parse::<Version>().unwrap()));
+///
+/// let req: Requirement = "1.14.x".parse().unwrap();
+/// assert!(req.matches(&"1.14.3".parse::<Version>().unwrap()));
+/// assert!(!req.matches(&"1.15.0".parse::<Version>().unwrap()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    pub constraints: Vec<Constraint>,
+}
+
+impl Requirement {
+    #[must_use]
+    pub fn matches(&self, version: &Version) -> bool {
+        self.constraints.iter().all(|constraint| constraint.matches(version))
+    }
+}
+
+impl FromStr for Requirement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let constraints = s
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Constraint>, String>>()
+            .map_err(|err| {
+                format!("Invalid version requirement '{s}': {err}")
+            })?;
+
+        Ok(Self { constraints })
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let constraints: Vec<String> =
+            self.constraints.iter().map(ToString::to_string).collect();
+
+        write!(f, "{}", constraints.join(","))
+    }
+}
+
+/// Split a dependency specification such as `gcc@>=12,<14` into the module
+/// name and an optional version [`Requirement`].
+///
+/// # Errors
+/// Errors if the requirement cannot be parsed.
+pub fn split_spec(spec: &str) -> Result<(&str, Option<Requirement>), String> {
+    match spec.split_once('@') {
+        Some((name, requirement)) => Ok((name, Some(requirement.parse()?))),
+        None => Ok((spec, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    fn matches(requirement: &str, v: &str) -> bool {
+        requirement.parse::<Requirement>().unwrap().matches(&version(v))
+    }
+
+    #[test]
+    fn empty_versions_are_rejected() {
+        assert!("".parse::<Version>().is_err());
+        assert!("  ".parse::<Version>().is_err());
+        assert!("1..2".parse::<Version>().is_err());
+        assert!("1.2.".parse::<Version>().is_err());
+        assert!("-1".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn numeric_segments_compare_numerically() {
+        assert!(version("0.3.9") < version("0.3.28"));
+        assert!(version("10") > version("9.99"));
+        assert_eq!(version("13"), version("13.0.0"));
+        assert_eq!(version("1_2-3"), version("1.2.3"));
+    }
+
+    #[test]
+    fn pre_releases_come_before_the_release() {
+        assert!(version("1.14.3-rc1") < version("1.14.3"));
+        assert!(version("1.0-alpha") < version("1.0-beta"));
+        assert!(version("1.0-beta") < version("1.0-rc1"));
+        assert!(version("1.0-rc.2") < version("1.0-rc.10"));
+        assert!(version("1.0-rc1") < version("1.0.1"));
+        assert!(version("2.0-rc1") > version("1.9"));
+    }
+
+    #[test]
+    fn text_segments_ignore_case() {
+        assert_eq!(version("1.0-RC1"), version("1.0-rc1"));
+        assert_eq!(version("Latest").to_string(), "latest");
+    }
+
+    #[test]
+    fn range_constraints() {
+        assert!(matches(">=12,<14", "12"));
+        assert!(matches(">=12,<14", "13.2.0"));
+        assert!(!matches(">=12,<14", "14"));
+        assert!(!matches(">=12,<14", "11.4"));
+        assert!(matches("!=13", "13.1"));
+        assert!(!matches("!=13", "13.0"));
+        assert!(matches("=1.2", "1.2.0"));
+        assert!(matches("==1.2", "1.2"));
+        assert!(matches("<=1.2", "1.2"));
+        assert!(matches(">1.2", "1.2.1"));
+    }
+
+    #[test]
+    fn pre_releases_do_not_satisfy_a_minimum_release() {
+        assert!(!matches(">=1.14.3", "1.14.3-rc1"));
+        assert!(matches("<1.14.3", "1.14.3-rc1"));
+    }
+
+    #[test]
+    fn prefix_constraints() {
+        assert!(matches("1.14", "1.14"));
+        assert!(matches("1.14.x", "1.14.0"));
+        assert!(matches("1.14.*", "1.14.3"));
+        assert!(!matches("1.14", "1.140"));
+        assert!(!matches("1.14.x", "1.15.0"));
+        assert!(!matches("1.14.3", "1.14"));
+    }
+
+    #[test]
+    fn invalid_requirements_are_rejected() {
+        assert!("".parse::<Requirement>().is_err());
+        assert!(">=".parse::<Requirement>().is_err());
+        assert!(">=12,".parse::<Requirement>().is_err());
+        assert!(split_spec("gcc@").is_err());
+    }
+
+    #[test]
+    fn split_specifications() {
+        let (name, requirement) = split_spec("gcc@>=12,<14").unwrap();
+        assert_eq!(name, "gcc");
+        assert_eq!(requirement.unwrap().to_string(), ">=12,<14");
+
+        let (name, requirement) = split_spec("hdf5").unwrap();
+        assert_eq!(name, "hdf5");
+        assert!(requirement.is_none());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for requirement in [">=12,<14", "1.14", "!=2.0.1", "=3"] {
+            let parsed: Requirement = requirement.parse().unwrap();
+            assert_eq!(parsed.to_string(), requirement);
+        }
+    }
+}